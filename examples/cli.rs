@@ -1,6 +1,10 @@
 use cid::Cid;
 use clap::{Parser, Subcommand, ValueEnum};
-use crsl_lib::convergence::metadata::ContentMetadata;
+use crsl_lib::convergence::{
+    diff::text::{TextHunk, WordChange},
+    metadata::{AutoMerge, ContentMetadata},
+    policies::text::TextMergePolicy,
+};
 use crsl_lib::crdt::{
     crdt_state::CrdtState,
     operation::{Operation, OperationType},
@@ -8,8 +12,8 @@ use crsl_lib::crdt::{
 };
 use crsl_lib::dasl::cid::ContentId;
 use crsl_lib::graph::{dag::DagGraph, storage::LeveldbNodeStorage};
-use crsl_lib::repo::Repo;
-use crsl_lib::storage::SharedLeveldb;
+use crsl_lib::repo::{AsOf, Repo};
+use crsl_lib::storage::{SharedLeveldb, Tag};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::path::{Path, PathBuf};
@@ -36,6 +40,12 @@ enum Commands {
         content: String,
         #[arg(short, long)]
         author: Option<String>,
+        /// Merge policy for concurrent edits ("lww", "text" or "text-manual").
+        #[arg(long, default_value = "lww")]
+        policy: String,
+        /// When concurrent edits are merged; defaults to before the next update.
+        #[arg(long, value_enum)]
+        auto_merge: Option<AutoMergeMode>,
     },
     Update {
         #[arg(short, long)]
@@ -46,9 +56,73 @@ enum Commands {
         author: Option<String>,
         #[arg(long)]
         parent: Option<String>,
+        /// Commit on top of this branch and advance it to the new version.
+        #[arg(short, long)]
+        branch: Option<String>,
+    },
+    /// Restore the content of an earlier version as a new version.
+    Revert {
+        #[arg(short, long)]
+        genesis_id: String,
+        /// The version CID or tag name to go back to.
+        target: String,
+        #[arg(short, long)]
+        author: Option<String>,
+    },
+    /// Merge all heads of a document with the given content.
+    Merge {
+        #[arg(short, long)]
+        genesis_id: String,
+        #[arg(short, long)]
+        content: String,
+        #[arg(short, long)]
+        author: Option<String>,
     },
     Show {
+        /// A genesis CID, or a version CID or tag name for that version.
         content_id: String,
+        /// Show the document as of a version CID, tag name or timestamp (nanoseconds
+        /// since epoch).
+        #[arg(long)]
+        at: Option<String>,
+    },
+    /// Show the line changes between two versions of a document.
+    Diff {
+        from: String,
+        to: String,
+        /// Mark changed words inside each hunk instead of whole lines.
+        #[arg(long)]
+        words: bool,
+    },
+    /// Show which version and author last changed each line of a document.
+    Blame {
+        /// A version CID, or a genesis CID for its latest version.
+        content_id: String,
+    },
+    /// Tag a version of a document; tags cannot be moved.
+    Tag {
+        #[arg(short, long)]
+        genesis_id: String,
+        name: String,
+        /// The version to tag; defaults to the latest version.
+        #[arg(long)]
+        at: Option<String>,
+        #[arg(short, long)]
+        author: Option<String>,
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// List the tags of a document.
+    Tags {
+        #[arg(short, long)]
+        genesis_id: String,
+    },
+    /// Manage named branches of a document.
+    Branch {
+        #[arg(short, long)]
+        genesis_id: String,
+        #[command(subcommand)]
+        action: BranchAction,
     },
     History {
         #[arg(short, long)]
@@ -81,7 +155,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             let mut repo = open_repo(repo_path)?;
 
             match other_command {
-                Commands::Create { content, author } => {
+                Commands::Create {
+                    content,
+                    author,
+                    policy,
+                    auto_merge,
+                } => {
                     let content_id_result = ContentId::new(content.as_bytes())?;
                     let cid = content_id_result.0;
 
@@ -89,7 +168,11 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     let op = Operation::new(cid, OperationType::Create(content.clone()), author);
 
-                    let version_cid = repo.commit_operation(op)?;
+                    let mut metadata = ContentMetadata::with_policy(policy);
+                    if let Some(mode) = auto_merge {
+                        metadata = metadata.with_auto_merge(mode.into());
+                    }
+                    let version_cid = repo.commit_create_with_metadata(op, metadata)?;
 
                     println!("✅ Created content:");
                     println!("   Content ID: {cid}");
@@ -101,6 +184,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     content,
                     author,
                     parent,
+                    branch,
                 } => {
                     let author = author.unwrap_or_else(|| "anonymous".to_string());
                     let genesis_cid = Cid::try_from(genesis_id.as_str())?;
@@ -120,7 +204,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                         println!("📝 Updated content:");
                     }
 
-                    let version_cid = repo.commit_operation(op)?;
+                    let version_cid = match &branch {
+                        Some(name) => {
+                            println!("   Branch: {name}");
+                            repo.commit_to_branch(name, op)?
+                        }
+                        None => repo.commit_operation(op)?,
+                    };
                     println!("   Genesis ID: {genesis_id}");
                     println!("   New Version: {version_cid}");
 
@@ -132,7 +222,74 @@ fn main() -> Result<(), Box<dyn Error>> {
                         }
                     }
                 }
-                Commands::Show { content_id } => {
+                Commands::Revert {
+                    genesis_id,
+                    target,
+                    author,
+                } => {
+                    let genesis_cid = Cid::try_from(genesis_id.as_str())?;
+                    let target_cid = resolve_version(&repo, &target)?;
+                    let author = author.unwrap_or_else(|| "anonymous".to_string());
+                    let version_cid = repo.revert(&genesis_cid, &target_cid, author)?;
+
+                    println!("⏪ Reverted content:");
+                    println!("   Genesis ID: {genesis_id}");
+                    println!("   Restored Version: {target_cid}");
+                    println!("   New Version: {version_cid}");
+                }
+                Commands::Merge {
+                    genesis_id,
+                    content,
+                    author,
+                } => {
+                    let author = author.unwrap_or_else(|| "anonymous".to_string());
+                    let genesis_cid = Cid::try_from(genesis_id.as_str())?;
+
+                    let version_cid = repo.resolve_conflict(&genesis_cid, content, author)?;
+                    println!("🔀 Merged heads:");
+                    println!("   Genesis ID: {genesis_id}");
+                    println!("   New Version: {version_cid}");
+                }
+                Commands::Show {
+                    content_id,
+                    at: None,
+                } if Cid::try_from(content_id.as_str()).is_err() => {
+                    let tag = find_tag(&repo, &content_id)?;
+                    println!("🏷️  Tag {}:", tag.name);
+                    println!("   Version: {}", tag.target);
+                    println!("   Genesis: {}", tag.genesis);
+                    println!("   Tagged by: {} at {}", tag.author, tag.timestamp);
+                    if let Some(message) = &tag.message {
+                        println!("   Message: {message}");
+                    }
+                    match repo.state_at(&tag.genesis, AsOf::Version(tag.target))? {
+                        Some(content) => println!("   Content: {content}"),
+                        None => println!("   Content: Not present at this version"),
+                    }
+                }
+                Commands::Show {
+                    content_id,
+                    at: Some(at),
+                } => {
+                    let cid = resolve_version(&repo, &content_id)?;
+                    let genesis_cid = repo.get_genesis(&cid)?;
+                    let as_of = match at.parse::<u64>() {
+                        Ok(timestamp) => AsOf::Timestamp(timestamp),
+                        Err(_) => AsOf::Version(resolve_version(&repo, &at)?),
+                    };
+
+                    println!("📄 Content details:");
+                    println!("   Genesis: {genesis_cid}");
+                    println!("   As of: {at}");
+                    match repo.state_at(&genesis_cid, as_of)? {
+                        Some(content) => println!("   Content: {content}"),
+                        None => println!("   Content: Not present at this point"),
+                    }
+                }
+                Commands::Show {
+                    content_id,
+                    at: None,
+                } => {
                     let cid = Cid::try_from(content_id.as_str())?;
 
                     // First try to get content from CRDT state
@@ -163,8 +320,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                                 println!("   Requested version: {cid} (genesis)");
                             }
 
-                            // Get and display latest version
-                            if let Some(latest_version) = repo.latest(&genesis_cid) {
+                            // Get and display latest version, merging heads that wait for a read
+                            if let Some(latest_version) = repo.latest_merged(&genesis_cid)? {
                                 if latest_version == cid {
                                     println!("   Latest version: {latest_version} ✅ (this is the latest)");
                                 } else {
@@ -195,12 +352,101 @@ fn main() -> Result<(), Box<dyn Error>> {
                         }
                     }
                 }
-                Commands::History { genesis_id, mode } => {
+                Commands::Diff { from, to, words } => {
+                    let from_cid = resolve_version(&repo, &from)?;
+                    let to_cid = resolve_version(&repo, &to)?;
+                    let patch = repo.diff(&from_cid, &to_cid)?;
+
+                    println!("🔍 Changes from {from} to {to}:");
+                    if patch.is_empty() {
+                        println!("   No changes");
+                    }
+                    for hunk in &patch.hunks {
+                        print_hunk(hunk, words);
+                    }
+                }
+                Commands::Blame { content_id } => {
+                    let cid = resolve_version(&repo, &content_id)?;
+                    let blame = repo.blame(&cid)?;
+
+                    println!("🔎 Line origins for {content_id}:");
+                    for (number, line) in blame.iter().enumerate() {
+                        println!(
+                            "{:>4} {} {:<10} {}",
+                            number + 1,
+                            line.version,
+                            line.author.as_deref().unwrap_or("unknown"),
+                            line.line.trim_end_matches('\n')
+                        );
+                    }
+                }
+                Commands::Tag {
+                    genesis_id,
+                    name,
+                    at,
+                    author,
+                    message,
+                } => {
+                    let genesis_cid = Cid::try_from(genesis_id.as_str())?;
+                    let target = match at {
+                        Some(at) => resolve_version(&repo, &at)?,
+                        None => repo.latest(&genesis_cid).ok_or("Genesis has no versions")?,
+                    };
+                    let author = author.unwrap_or_else(|| "anonymous".to_string());
+                    let tag = repo.create_tag(&genesis_cid, &name, &target, author, message)?;
+                    println!("🏷️  Tagged {} as {}", tag.target, tag.name);
+                }
+                Commands::Tags { genesis_id } => {
+                    let genesis_cid = Cid::try_from(genesis_id.as_str())?;
+                    display_tags(&repo, &genesis_cid)?;
+                }
+                Commands::Branch { genesis_id, action } => {
                     let genesis_cid = Cid::try_from(genesis_id.as_str())?;
+                    match action {
+                        BranchAction::List => {
+                            let branches = repo.branches(&genesis_cid)?;
+                            println!("🌿 Branches of {genesis_id}:");
+                            if branches.is_empty() {
+                                println!("   No branches");
+                            }
+                            for (name, tip) in branches {
+                                println!("   {name} -> {tip}");
+                            }
+                        }
+                        BranchAction::Create { name, at } => {
+                            let target = match at {
+                                Some(at) => resolve_version(&repo, &at)?,
+                                None => {
+                                    repo.latest(&genesis_cid).ok_or("Genesis has no versions")?
+                                }
+                            };
+                            repo.create_branch(&genesis_cid, &name, &target)?;
+                            println!("🌿 Created branch {name} at {target}");
+                        }
+                        BranchAction::Move { name, to } => {
+                            let target = resolve_version(&repo, &to)?;
+                            let current = repo
+                                .branch(&genesis_cid, &name)?
+                                .ok_or_else(|| format!("Unknown branch: {name}"))?;
+                            repo.move_branch(&genesis_cid, &name, &current, &target)?;
+                            println!("🌿 Moved branch {name} from {current} to {target}");
+                        }
+                        BranchAction::Delete { name } => {
+                            let current = repo
+                                .branch(&genesis_cid, &name)?
+                                .ok_or_else(|| format!("Unknown branch: {name}"))?;
+                            repo.delete_branch(&genesis_cid, &name, &current)?;
+                            println!("🗑️  Deleted branch {name} (was at {current})");
+                        }
+                    }
+                }
+                Commands::History { genesis_id, mode } => {
+                    let genesis_cid = repo.get_genesis(&resolve_version(&repo, &genesis_id)?)?;
                     let result = match mode {
                         HistoryMode::Tree => display_branching_history(&repo, &genesis_cid),
                         HistoryMode::Linear => display_linear_history(&repo, &genesis_cid),
-                    };
+                    }
+                    .and_then(|()| display_tags(&repo, &genesis_cid));
 
                     if let Err(e) = result {
                         eprintln!("❌ Error rendering history: {e}");
@@ -214,11 +460,78 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn print_hunk(hunk: &TextHunk, words: bool) {
+    println!(
+        "@@ -{},{} +{},{} @@",
+        hunk.old_start + 1,
+        hunk.removed.len(),
+        hunk.new_start + 1,
+        hunk.added.len()
+    );
+    if words {
+        let mut rendered = String::new();
+        for change in hunk.words() {
+            match change {
+                WordChange::Equal(text) => rendered.push_str(&text),
+                WordChange::Removed(text) => rendered.push_str(&format!("[-{text}-]")),
+                WordChange::Added(text) => rendered.push_str(&format!("{{+{text}+}}")),
+            }
+        }
+        print!("{rendered}");
+        if !rendered.ends_with('\n') {
+            println!();
+        }
+        return;
+    }
+    for line in &hunk.removed {
+        println!("-{}", line.trim_end_matches('\n'));
+    }
+    for line in &hunk.added {
+        println!("+{}", line.trim_end_matches('\n'));
+    }
+}
+
 fn open_repo(repo_path: &Path) -> Result<CliRepo, Box<dyn Error>> {
     let shared = SharedLeveldb::open(repo_path.join("store"))?;
     let state = CrdtState::new(LeveldbStorage::new(shared.clone()));
     let dag = DagGraph::new(LeveldbNodeStorage::new(shared));
-    Ok(Repo::new(state, dag))
+    let mut repo = Repo::new(state, dag);
+    repo.register_policy(Box::new(TextMergePolicy::new()));
+    repo.register_policy(Box::new(TextMergePolicy::manual()));
+    Ok(repo)
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum AutoMergeMode {
+    Always,
+    Never,
+    OnRead,
+}
+
+impl From<AutoMergeMode> for AutoMerge {
+    fn from(mode: AutoMergeMode) -> Self {
+        match mode {
+            AutoMergeMode::Always => AutoMerge::Always,
+            AutoMergeMode::Never => AutoMerge::Never,
+            AutoMergeMode::OnRead => AutoMerge::OnRead,
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+enum BranchAction {
+    /// List the branches and their tips.
+    List,
+    /// Create a branch at a version (CID or tag), or at the latest version.
+    Create {
+        name: String,
+        #[arg(long)]
+        at: Option<String>,
+    },
+    /// Point a branch at another version (CID or tag).
+    Move { name: String, to: String },
+    /// Delete a branch; its versions are kept.
+    Delete { name: String },
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -271,13 +584,16 @@ fn print_branching_node(
                 "🌱"
             } else if n.parents().len() > 1 {
                 "🔀"
+            } else if n.metadata().reverted_to().is_some() {
+                "⏪"
             } else {
                 "🧩"
             };
             let summary = clean_payload_summary(n.payload());
             let label = format!("node{}", *counter);
             *counter += 1;
-            (marker, format!("{label}: {current} | {summary}"))
+            let revert = revert_note(n.metadata());
+            (marker, format!("{label}: {current} | {summary}{revert}"))
         }
         None => {
             let label = format!("node{}", *counter);
@@ -366,13 +682,19 @@ fn display_linear_history(repo: &CliRepo, genesis: &Cid) -> Result<(), Box<dyn E
                     "🌱"
                 } else if n.parents().len() > 1 {
                     "🔀"
+                } else if n.metadata().reverted_to().is_some() {
+                    "⏪"
                 } else if index == path.len() - 1 {
                     "✨"
                 } else {
                     "🧩"
                 };
                 let summary = clean_payload_summary(n.payload());
-                (marker, format!("node{}: {cid} | {summary}", index + 1))
+                let revert = revert_note(n.metadata());
+                (
+                    marker,
+                    format!("node{}: {cid} | {summary}{revert}", index + 1),
+                )
             }
             None => ("❓", format!("node{}: {cid} (missing)", index + 1)),
         };
@@ -382,6 +704,47 @@ fn display_linear_history(repo: &CliRepo, genesis: &Cid) -> Result<(), Box<dyn E
     Ok(())
 }
 
+fn display_tags(repo: &CliRepo, genesis: &Cid) -> Result<(), Box<dyn Error>> {
+    let tags = repo.tags(genesis)?;
+    if tags.is_empty() {
+        return Ok(());
+    }
+    println!("🏷️  Tags:");
+    for tag in tags {
+        let message = tag.message.map(|m| format!(" | {m}")).unwrap_or_default();
+        println!(
+            "   {} -> {} ({}, {}){message}",
+            tag.name, tag.target, tag.author, tag.timestamp
+        );
+    }
+    Ok(())
+}
+
+/// Looks up a tag by name; the name must be unique across documents.
+fn find_tag(repo: &CliRepo, name: &str) -> Result<Tag, Box<dyn Error>> {
+    let mut tags = repo.find_tags(name)?;
+    match tags.len() {
+        0 => Err(format!("Not a CID or known tag: {name}").into()),
+        1 => Ok(tags.remove(0)),
+        _ => Err(format!("Tag {name} exists in several documents; use a version CID").into()),
+    }
+}
+
+/// Parses a version CID, or resolves a tag name to the version it points at.
+fn resolve_version(repo: &CliRepo, spec: &str) -> Result<Cid, Box<dyn Error>> {
+    match Cid::try_from(spec) {
+        Ok(cid) => Ok(cid),
+        Err(_) => Ok(find_tag(repo, spec)?.target),
+    }
+}
+
+fn revert_note(metadata: &ContentMetadata) -> String {
+    metadata
+        .reverted_to()
+        .map(|target| format!(" (reverts to {target})"))
+        .unwrap_or_default()
+}
+
 fn clean_payload_summary(payload: &str) -> String {
     let trimmed = payload.trim();
     if trimmed.len() <= 48 {
@@ -0,0 +1,149 @@
+pub mod json;
+pub mod text;
+
+/// Payloads that can describe the change between two versions as a patch.
+///
+/// Patches are replayed on top of other concurrent changes during three-way merges,
+/// so [`Diffable::apply`] must accept values that differ from the one the patch was
+/// computed against. Changes to disjoint parts should compose; how overlapping
+/// changes are settled is up to the implementation.
+pub trait Diffable: Sized {
+    /// Description of the changes between two versions.
+    type Patch;
+
+    /// Computes the patch that turns `self` into `other`.
+    fn diff(&self, other: &Self) -> Self::Patch;
+
+    /// Applies a patch, producing a new version.
+    fn apply(&self, patch: &Self::Patch) -> Self;
+
+    /// Applies a patch, or returns `None` when part of it no longer fits `self`
+    /// and [`Self::apply`] would drop it.
+    ///
+    /// The default accepts every patch.
+    fn try_apply(&self, patch: &Self::Patch) -> Option<Self> {
+        Some(self.apply(patch))
+    }
+}
+
+/// Returns the index pairs `(i, j)` of a longest common subsequence of `a` and `b`,
+/// in increasing order.
+///
+/// Uses Myers' O((N + M) D) algorithm after stripping the common prefix and suffix.
+pub fn matching_pairs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    pairs.extend(
+        myers_pairs(a_mid, b_mid)
+            .into_iter()
+            .map(|(i, j)| (i + prefix, j + prefix)),
+    );
+    let (a_tail, b_tail) = (a.len() - suffix, b.len() - suffix);
+    pairs.extend((0..suffix).map(|k| (a_tail + k, b_tail + k)));
+    pairs
+}
+
+fn myers_pairs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    if max == 0 {
+        return Vec::new();
+    }
+
+    // v[k + max] holds the furthest x reached on diagonal k. trace[d] keeps the
+    // diagonals -d..=d as they were before round d, for backtracking.
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(v[(max - d) as usize..=(max + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, row) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| row[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_x = at(prev_k);
+            (prev_x, prev_x - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    pairs.reverse();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn common<T: PartialEq + Clone>(a: &[T], b: &[T]) -> Vec<T> {
+        let pairs = matching_pairs(a, b);
+        for window in pairs.windows(2) {
+            assert!(window[0].0 < window[1].0 && window[0].1 < window[1].1);
+        }
+        for &(i, j) in &pairs {
+            assert!(a[i] == b[j]);
+        }
+        pairs.into_iter().map(|(i, _)| a[i].clone()).collect()
+    }
+
+    #[test]
+    fn matching_pairs_finds_longest_common_subsequence() {
+        let a: Vec<char> = "ABCABBA".chars().collect();
+        let b: Vec<char> = "CBABAC".chars().collect();
+        assert_eq!(common(&a, &b).len(), 4);
+
+        let a = ["keep", "drop", "same", "tail"];
+        let b = ["keep", "same", "new", "tail"];
+        assert_eq!(common(&a, &b), vec!["keep", "same", "tail"]);
+    }
+
+    #[test]
+    fn matching_pairs_handles_empty_and_disjoint_inputs() {
+        assert!(matching_pairs::<u8>(&[], &[]).is_empty());
+        assert!(matching_pairs(&[1, 2], &[]).is_empty());
+        assert!(matching_pairs(&[1, 2], &[3, 4]).is_empty());
+        assert_eq!(matching_pairs(&[1, 2], &[1, 2]), vec![(0, 0), (1, 1)]);
+    }
+}
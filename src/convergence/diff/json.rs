@@ -0,0 +1,248 @@
+use crate::convergence::diff::{matching_pairs, Diffable};
+use serde_json::{Map, Value};
+
+/// A JSON Patch (RFC 6902) operation. Paths are JSON Pointers (RFC 6901).
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonPatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+impl JsonPatchOp {
+    /// Returns the JSON Pointer the operation targets.
+    pub fn path(&self) -> &str {
+        match self {
+            JsonPatchOp::Add { path, .. }
+            | JsonPatchOp::Remove { path }
+            | JsonPatchOp::Replace { path, .. } => path,
+        }
+    }
+}
+
+/// Structural diff producing JSON Patch operations, applied in order.
+///
+/// Objects are compared key by key and arrays element by element, so a change deep in
+/// a document yields a single operation. [`Diffable::apply`] skips operations whose
+/// target no longer exists, and an `add` to an existing object member replaces it.
+impl Diffable for Value {
+    type Patch = Vec<JsonPatchOp>;
+
+    fn diff(&self, other: &Self) -> Vec<JsonPatchOp> {
+        let mut ops = Vec::new();
+        diff_at("", self, other, &mut ops);
+        ops
+    }
+
+    fn apply(&self, patch: &Vec<JsonPatchOp>) -> Self {
+        let mut document = self.clone();
+        for op in patch {
+            apply_op(&mut document, op);
+        }
+        document
+    }
+}
+
+fn diff_at(path: &str, old: &Value, new: &Value, ops: &mut Vec<JsonPatchOp>) {
+    match (old, new) {
+        _ if old == new => {}
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, value) in old {
+                let child = pointer(path, key);
+                match new.get(key) {
+                    Some(other) => diff_at(&child, value, other, ops),
+                    None => ops.push(JsonPatchOp::Remove { path: child }),
+                }
+            }
+            for (key, value) in new {
+                if !old.contains_key(key) {
+                    ops.push(JsonPatchOp::Add {
+                        path: pointer(path, key),
+                        value: value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => diff_arrays(path, old, new, ops),
+        _ => ops.push(JsonPatchOp::Replace {
+            path: path.to_string(),
+            value: new.clone(),
+        }),
+    }
+}
+
+/// Emits removals and insertions around the longest common subsequence. Runs where
+/// as many elements are removed as added are diffed element by element instead.
+fn diff_arrays(path: &str, old: &[Value], new: &[Value], ops: &mut Vec<JsonPatchOp>) {
+    // Position in the array as it looks after the operations emitted so far.
+    let mut position = 0;
+    let (mut old_pos, mut new_pos) = (0, 0);
+    let sentinel = (old.len(), new.len());
+    for (old_index, new_index) in matching_pairs(old, new).into_iter().chain([sentinel]) {
+        let removed = &old[old_pos..old_index];
+        let added = &new[new_pos..new_index];
+        if removed.len() == added.len() {
+            for (value, other) in removed.iter().zip(added) {
+                diff_at(&pointer(path, &position.to_string()), value, other, ops);
+                position += 1;
+            }
+        } else {
+            for _ in removed {
+                ops.push(JsonPatchOp::Remove {
+                    path: pointer(path, &position.to_string()),
+                });
+            }
+            for value in added {
+                ops.push(JsonPatchOp::Add {
+                    path: pointer(path, &position.to_string()),
+                    value: value.clone(),
+                });
+                position += 1;
+            }
+        }
+        position += 1;
+        old_pos = old_index + 1;
+        new_pos = new_index + 1;
+    }
+}
+
+fn pointer(parent: &str, token: &str) -> String {
+    format!("{parent}/{}", token.replace('~', "~0").replace('/', "~1"))
+}
+
+fn apply_op(document: &mut Value, op: &JsonPatchOp) {
+    let path = op.path();
+    if path.is_empty() {
+        match op {
+            JsonPatchOp::Add { value, .. } | JsonPatchOp::Replace { value, .. } => {
+                *document = value.clone();
+            }
+            JsonPatchOp::Remove { .. } => *document = Value::Null,
+        }
+        return;
+    }
+
+    let (parent_path, token) = path.rsplit_once('/').unwrap_or(("", path));
+    let token = token.replace("~1", "/").replace("~0", "~");
+    let Some(parent) = document.pointer_mut(parent_path) else {
+        return;
+    };
+    match parent {
+        Value::Object(members) => apply_to_object(members, &token, op),
+        Value::Array(elements) => apply_to_array(elements, &token, op),
+        _ => {}
+    }
+}
+
+fn apply_to_object(members: &mut Map<String, Value>, key: &str, op: &JsonPatchOp) {
+    match op {
+        JsonPatchOp::Add { value, .. } => {
+            members.insert(key.to_string(), value.clone());
+        }
+        JsonPatchOp::Replace { value, .. } => {
+            if let Some(member) = members.get_mut(key) {
+                *member = value.clone();
+            }
+        }
+        JsonPatchOp::Remove { .. } => {
+            members.remove(key);
+        }
+    }
+}
+
+fn apply_to_array(elements: &mut Vec<Value>, token: &str, op: &JsonPatchOp) {
+    let index = if token == "-" {
+        elements.len()
+    } else {
+        match token.parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => return,
+        }
+    };
+    match op {
+        JsonPatchOp::Add { value, .. } => {
+            elements.insert(index.min(elements.len()), value.clone());
+        }
+        JsonPatchOp::Replace { value, .. } => {
+            if let Some(element) = elements.get_mut(index) {
+                *element = value.clone();
+            }
+        }
+        JsonPatchOp::Remove { .. } => {
+            if index < elements.len() {
+                elements.remove(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff_emits_pointer_operations() {
+        let old = json!({"title": "draft", "meta": {"a/b": 1, "gone": true}, "tags": ["x", "y"]});
+        let new =
+            json!({"title": "final", "meta": {"a/b": 2}, "tags": ["x", "z", "y"], "new": null});
+
+        let patch = old.diff(&new);
+        assert_eq!(
+            patch,
+            vec![
+                JsonPatchOp::Replace {
+                    path: "/meta/a~1b".into(),
+                    value: json!(2)
+                },
+                JsonPatchOp::Remove {
+                    path: "/meta/gone".into()
+                },
+                JsonPatchOp::Add {
+                    path: "/tags/1".into(),
+                    value: json!("z")
+                },
+                JsonPatchOp::Replace {
+                    path: "/title".into(),
+                    value: json!("final")
+                },
+                JsonPatchOp::Add {
+                    path: "/new".into(),
+                    value: Value::Null
+                },
+            ]
+        );
+        assert_eq!(old.apply(&patch), new);
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn array_edits_roundtrip() {
+        let cases = [
+            (json!([1, 2, 3, 4]), json!([2, 4, 5])),
+            (
+                json!([{"id": 1, "v": "a"}, {"id": 2}]),
+                json!([{"id": 1, "v": "b"}, {"id": 2}]),
+            ),
+            (json!([]), json!([1, 2])),
+            (json!([1, 2]), json!({"now": "object"})),
+        ];
+        for (old, new) in cases {
+            assert_eq!(old.apply(&old.diff(&new)), new, "{old} -> {new}");
+        }
+    }
+
+    #[test]
+    fn apply_composes_disjoint_changes() {
+        let base = json!({"a": 1, "b": {"c": 1}});
+        let ours = json!({"a": 2, "b": {"c": 1}});
+        let theirs = json!({"a": 1, "b": {"c": 3, "d": 4}});
+
+        assert_eq!(
+            ours.apply(&base.diff(&theirs)),
+            json!({"a": 2, "b": {"c": 3, "d": 4}})
+        );
+        // Operations on members removed concurrently are skipped.
+        assert_eq!(json!({"a": 2}).apply(&base.diff(&theirs)), json!({"a": 2}));
+    }
+}
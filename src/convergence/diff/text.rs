@@ -0,0 +1,272 @@
+use crate::convergence::diff::{matching_pairs, Diffable};
+
+/// Line-based changes between two texts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextPatch {
+    pub hunks: Vec<TextHunk>,
+}
+
+/// A run of consecutive lines replaced by other lines.
+///
+/// Lines keep their `\n` terminator, so joining them reproduces the text exactly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextHunk {
+    /// Index of the first removed line in the old text.
+    pub old_start: usize,
+    /// Index of the first added line in the new text.
+    pub new_start: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+/// One step of a word-level comparison.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WordChange {
+    Equal(String),
+    Removed(String),
+    Added(String),
+}
+
+impl TextPatch {
+    /// Returns `true` if the texts are identical.
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+}
+
+impl TextHunk {
+    /// Compares the removed and added lines word by word.
+    ///
+    /// Words are runs of non-whitespace; whitespace runs are tokens of their own, so
+    /// concatenating the equal and removed (or added) parts gives back the old (or new)
+    /// lines. Adjacent tokens of the same kind are joined.
+    pub fn words(&self) -> Vec<WordChange> {
+        let old = self.removed.concat();
+        let new = self.added.concat();
+        let old_words = split_words(&old);
+        let new_words = split_words(&new);
+
+        let mut changes: Vec<WordChange> = Vec::new();
+        let (mut old_pos, mut new_pos) = (0, 0);
+        let sentinel = (old_words.len(), new_words.len());
+        for (old_index, new_index) in matching_pairs(&old_words, &new_words)
+            .into_iter()
+            .chain([sentinel])
+        {
+            for word in &old_words[old_pos..old_index] {
+                push_word(&mut changes, WordChange::Removed(word.to_string()));
+            }
+            for word in &new_words[new_pos..new_index] {
+                push_word(&mut changes, WordChange::Added(word.to_string()));
+            }
+            if let Some(word) = old_words.get(old_index) {
+                push_word(&mut changes, WordChange::Equal(word.to_string()));
+            }
+            old_pos = old_index + 1;
+            new_pos = new_index + 1;
+        }
+        changes
+    }
+}
+
+/// Line-based diff. [`Diffable::apply`] relocates hunks whose lines moved because of
+/// other changes and skips hunks whose removed lines are gone; [`Diffable::try_apply`]
+/// returns `None` for such patches instead.
+impl Diffable for String {
+    type Patch = TextPatch;
+
+    fn diff(&self, other: &Self) -> TextPatch {
+        let old: Vec<&str> = self.split_inclusive('\n').collect();
+        let new: Vec<&str> = other.split_inclusive('\n').collect();
+
+        let mut hunks = Vec::new();
+        let (mut old_pos, mut new_pos) = (0, 0);
+        let sentinel = (old.len(), new.len());
+        for (old_index, new_index) in matching_pairs(&old, &new).into_iter().chain([sentinel]) {
+            if old_index > old_pos || new_index > new_pos {
+                hunks.push(TextHunk {
+                    old_start: old_pos,
+                    new_start: new_pos,
+                    removed: old[old_pos..old_index]
+                        .iter()
+                        .map(|l| l.to_string())
+                        .collect(),
+                    added: new[new_pos..new_index]
+                        .iter()
+                        .map(|l| l.to_string())
+                        .collect(),
+                });
+            }
+            old_pos = old_index + 1;
+            new_pos = new_index + 1;
+        }
+        TextPatch { hunks }
+    }
+
+    fn apply(&self, patch: &TextPatch) -> Self {
+        apply_hunks(self, patch, false).unwrap_or_default()
+    }
+
+    fn try_apply(&self, patch: &TextPatch) -> Option<Self> {
+        apply_hunks(self, patch, true)
+    }
+}
+
+/// Applies the hunks of `patch` to `text`. A hunk whose removed lines are missing is
+/// skipped, or makes the whole call return `None` when `strict` is set.
+fn apply_hunks(text: &str, patch: &TextPatch, strict: bool) -> Option<String> {
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(str::to_string).collect();
+    // Shift between positions in the patch's old text and in `lines`.
+    let mut offset: isize = 0;
+    for hunk in &patch.hunks {
+        let expected = (hunk.old_start as isize + offset).clamp(0, lines.len() as isize);
+        let Some(at) = find_block(&lines, &hunk.removed, expected as usize) else {
+            if strict {
+                return None;
+            }
+            continue;
+        };
+        let mut added = hunk.added.clone();
+        // Only the last line can lack a newline; keep lines apart when appending
+        // after it or inserting a block that lacked one before other lines.
+        if let Some(previous) = at.checked_sub(1).and_then(|i| lines.get_mut(i)) {
+            if !previous.ends_with('\n') && !added.is_empty() {
+                previous.push('\n');
+            }
+        }
+        if let Some(last) = added.last_mut() {
+            if !last.ends_with('\n') && at + hunk.removed.len() < lines.len() {
+                last.push('\n');
+            }
+        }
+        offset = at as isize - hunk.old_start as isize + added.len() as isize
+            - hunk.removed.len() as isize;
+        lines.splice(at..at + hunk.removed.len(), added);
+    }
+    Some(lines.concat())
+}
+
+/// Finds `block` in `lines`, preferring the position closest to `expected`.
+fn find_block(lines: &[String], block: &[String], expected: usize) -> Option<usize> {
+    if block.is_empty() {
+        return Some(expected.min(lines.len()));
+    }
+    let matches = |at: usize| lines.get(at..at + block.len()) == Some(block);
+    (0..=lines.len())
+        .flat_map(|distance| {
+            [
+                expected.checked_add(distance),
+                expected.checked_sub(distance),
+            ]
+        })
+        .flatten()
+        .find(|&at| matches(at))
+}
+
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (index, ch) in text.char_indices() {
+        let space = ch.is_whitespace();
+        if in_space.is_some_and(|previous| previous != space) {
+            words.push(&text[start..index]);
+            start = index;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+fn push_word(changes: &mut Vec<WordChange>, change: WordChange) {
+    match (changes.last_mut(), change) {
+        (Some(WordChange::Equal(text)), WordChange::Equal(word))
+        | (Some(WordChange::Removed(text)), WordChange::Removed(word))
+        | (Some(WordChange::Added(text)), WordChange::Added(word)) => text.push_str(&word),
+        (_, change) => changes.push(change),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_line_hunks() {
+        let old = "one\ntwo\nthree\nfour\n".to_string();
+        let new = "one\n2\nthree\nfour\nfive\n".to_string();
+
+        let patch = old.diff(&new);
+        assert_eq!(
+            patch.hunks,
+            vec![
+                TextHunk {
+                    old_start: 1,
+                    new_start: 1,
+                    removed: vec!["two\n".into()],
+                    added: vec!["2\n".into()],
+                },
+                TextHunk {
+                    old_start: 4,
+                    new_start: 4,
+                    removed: vec![],
+                    added: vec!["five\n".into()],
+                },
+            ]
+        );
+        assert_eq!(old.apply(&patch), new);
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn hunk_words_mark_changed_words() {
+        let old = "the quick brown fox\n".to_string();
+        let new = "the slow brown fox\n".to_string();
+
+        let patch = old.diff(&new);
+        assert_eq!(
+            patch.hunks[0].words(),
+            vec![
+                WordChange::Equal("the ".into()),
+                WordChange::Removed("quick".into()),
+                WordChange::Added("slow".into()),
+                WordChange::Equal(" brown fox\n".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_relocates_hunks_after_other_changes() {
+        let base = "a\nb\nc\nd\n".to_string();
+        let ours = "intro\na\nb\nc\nd\n".to_string();
+        let theirs = "a\nb\nc\nD\n".to_string();
+
+        assert_eq!(ours.apply(&base.diff(&theirs)), "intro\na\nb\nc\nD\n");
+    }
+
+    #[test]
+    fn apply_handles_missing_trailing_newline() {
+        let base = "a\nb".to_string();
+        let appended = "a\nb\nc".to_string();
+        let edited = "A\nb".to_string();
+
+        assert_eq!(base.apply(&base.diff(&appended)), appended);
+        assert_eq!(edited.apply(&base.diff(&appended)), "A\nb\nc");
+        assert_eq!(base.apply(&base.diff(&String::new())), "");
+    }
+
+    #[test]
+    fn try_apply_rejects_hunks_whose_lines_are_gone() {
+        let base = "a\nb\nc\n".to_string();
+        let ours = "a\nB\nc\n".to_string();
+        let theirs = "a\nbee\nc\n".to_string();
+
+        let patch = base.diff(&theirs);
+        assert_eq!(ours.try_apply(&patch), None);
+        assert_eq!(ours.apply(&patch), ours);
+        assert_eq!(base.try_apply(&patch).as_deref(), Some("a\nbee\nc\n"));
+    }
+}
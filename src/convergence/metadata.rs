@@ -1,3 +1,5 @@
+use crate::masl::Bundle;
+use cid::Cid;
 use serde::{Deserialize, Serialize};
 
 /// Built-in and custom convergence policy types.
@@ -24,19 +26,71 @@ impl From<String> for PolicyType {
     }
 }
 
+/// How a merge node came about.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MergeOrigin {
+    /// Produced by the document's merge policy.
+    Auto,
+    /// Committed with a payload chosen by the application or user.
+    Manual,
+}
+
+/// When concurrent heads of a document are merged.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AutoMerge {
+    /// Merge before any commit that does not name its parents.
+    #[default]
+    Always,
+    /// Never merge automatically; commits without parents fail while there are
+    /// several heads, which stay until [`crate::repo::Repo::merge`] is called.
+    Never,
+    /// Let commits without parents extend the latest head and merge when the
+    /// document is read through [`crate::repo::Repo::latest_merged`].
+    OnRead,
+}
+
 /// Metadata that stores information required for convergence policies.
+///
+/// The metadata is serialized into every node, so it is part of the node's CID.
+/// Fields added after the first release are skipped while they hold their default
+/// value, which keeps nodes that do not use them byte-identical to older nodes.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ContentMetadata {
     /// Policy type (e.g. Lww, custom named policy).
     ///
     /// When this is `None`, it falls back to the default policy (currently Lww).
     policy_type: Option<PolicyType>,
+    /// Resources (attachments) referenced by the content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resources: Option<Bundle>,
+    /// Whether a merge node was produced by the merge policy or committed by hand.
+    /// Set on merge nodes only; children do not inherit it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merge_origin: Option<MergeOrigin>,
+    /// Per-document auto-merge mode; `None` uses the repository default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auto_merge: Option<AutoMerge>,
+    /// The version whose payload a revert node restored. Set on revert nodes only;
+    /// children do not inherit it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reverted_to: Option<Cid>,
+    /// Set on delete nodes only, whose payload is the last one before the delete;
+    /// children do not inherit it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
 }
 
 impl ContentMetadata {
     /// Create metadata with the default LWW policy.
     pub fn new() -> Self {
-        Self { policy_type: None }
+        Self {
+            policy_type: None,
+            resources: None,
+            merge_origin: None,
+            auto_merge: None,
+            reverted_to: None,
+            deleted: false,
+        }
     }
 
     /// Create metadata that uses the specified policy.
@@ -45,6 +99,7 @@ impl ContentMetadata {
     pub fn with_policy(policy_type: impl Into<PolicyType>) -> Self {
         Self {
             policy_type: Some(policy_type.into()),
+            ..Self::new()
         }
     }
 
@@ -55,6 +110,73 @@ impl ContentMetadata {
             Some(PolicyType::Custom(name)) => name.as_str(),
         }
     }
+
+    /// Attach a bundle of MASL resources.
+    pub fn with_resources(mut self, resources: Bundle) -> Self {
+        self.resources = Some(resources);
+        self
+    }
+
+    /// Return the attached resources, if any.
+    pub fn resources(&self) -> Option<&Bundle> {
+        self.resources.as_ref()
+    }
+
+    /// Override the repository's auto-merge mode for this document.
+    pub fn with_auto_merge(mut self, mode: AutoMerge) -> Self {
+        self.auto_merge = Some(mode);
+        self
+    }
+
+    /// Return the document's auto-merge mode, or `None` to use the repository default.
+    pub fn auto_merge(&self) -> Option<AutoMerge> {
+        self.auto_merge
+    }
+
+    /// Mark the metadata as belonging to a merge node.
+    pub fn with_merge_origin(mut self, origin: MergeOrigin) -> Self {
+        self.merge_origin = Some(origin);
+        self
+    }
+
+    /// Return how the node was merged, or `None` for non-merge nodes and merges
+    /// created before the origin was recorded.
+    pub fn merge_origin(&self) -> Option<MergeOrigin> {
+        self.merge_origin
+    }
+
+    /// Mark the metadata as belonging to a node that restores the payload of `target`.
+    pub fn with_reverted_to(mut self, target: Cid) -> Self {
+        self.reverted_to = Some(target);
+        self
+    }
+
+    /// Return the version a revert node restored, or `None` for other nodes.
+    pub fn reverted_to(&self) -> Option<Cid> {
+        self.reverted_to
+    }
+
+    /// Mark the metadata as belonging to a node that deletes the document.
+    pub fn with_deleted(mut self) -> Self {
+        self.deleted = true;
+        self
+    }
+
+    /// Return whether the node deletes the document. Delete nodes created before
+    /// deletes were marked return `false`.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Return the metadata a child of this node inherits.
+    pub fn inherited(&self) -> Self {
+        Self {
+            merge_origin: None,
+            reverted_to: None,
+            deleted: false,
+            ..self.clone()
+        }
+    }
 }
 
 impl Default for ContentMetadata {
@@ -1,4 +1,6 @@
+pub mod diff;
 pub mod metadata;
 pub mod policies;
 pub mod policy;
+pub mod registry;
 pub mod resolver;
@@ -0,0 +1,241 @@
+use crate::convergence::policy::{MergeContext, MergeOutcome, MergePolicy, ResolveInput};
+use serde_json::{Map, Value};
+
+/// How [`JsonMergePolicy`] merges arrays that several heads changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// Arrays are leaf values: the latest change wins.
+    #[default]
+    Replace,
+    /// Arrays are sets: elements added by any head are kept and elements removed by
+    /// any head are dropped. Order follows first appearance in base, then heads.
+    Union,
+}
+
+/// Merges JSON documents key by key.
+///
+/// Objects are merged recursively. For every leaf, only the heads that changed it
+/// relative to the common ancestor compete, and the one with the greatest node
+/// timestamp (ties broken by CID) wins. A removed key counts as a change, so deletes
+/// survive concurrent edits to other fields. Without a common ancestor every present
+/// value counts as a change.
+#[derive(Debug, Default)]
+pub struct JsonMergePolicy {
+    arrays: ArrayMerge,
+}
+
+impl JsonMergePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects how arrays are merged.
+    pub fn with_arrays(mut self, arrays: ArrayMerge) -> Self {
+        self.arrays = arrays;
+        self
+    }
+
+    fn merge_value(&self, base: Option<&Value>, heads: &[Option<&Value>]) -> Option<Value> {
+        let changed: Vec<Option<&Value>> = heads
+            .iter()
+            .copied()
+            .filter(|value| *value != base)
+            .collect();
+        let Some(latest) = changed.last() else {
+            return base.cloned();
+        };
+
+        let base_is = |check: fn(&Value) -> bool| base.map_or(true, check);
+        if changed
+            .iter()
+            .all(|value| value.is_some_and(Value::is_object))
+            && base_is(Value::is_object)
+        {
+            return Some(Value::Object(self.merge_objects(base, heads)));
+        }
+        if self.arrays == ArrayMerge::Union
+            && changed
+                .iter()
+                .all(|value| value.is_some_and(Value::is_array))
+            && base_is(Value::is_array)
+        {
+            return Some(Value::Array(union_arrays(base, &changed)));
+        }
+        latest.cloned()
+    }
+
+    fn merge_objects(&self, base: Option<&Value>, heads: &[Option<&Value>]) -> Map<String, Value> {
+        let base = base.and_then(Value::as_object);
+        let heads: Vec<Option<&Map<String, Value>>> = heads
+            .iter()
+            .map(|value| value.and_then(Value::as_object))
+            .collect();
+
+        let mut keys: Vec<&String> = base.into_iter().flat_map(Map::keys).collect();
+        for head in heads.iter().flatten() {
+            keys.extend(head.keys());
+        }
+        keys.sort();
+        keys.dedup();
+
+        let mut merged = Map::new();
+        for key in keys {
+            let values: Vec<Option<&Value>> = heads
+                .iter()
+                .map(|head| head.and_then(|object| object.get(key)))
+                .collect();
+            if let Some(value) = self.merge_value(base.and_then(|object| object.get(key)), &values)
+            {
+                merged.insert(key.clone(), value);
+            }
+        }
+        merged
+    }
+}
+
+impl MergePolicy<Value> for JsonMergePolicy {
+    fn resolve(&self, nodes: &[ResolveInput<Value>]) -> MergeOutcome<Value> {
+        self.resolve_with_base(&MergeContext::new(nodes.to_vec(), None))
+    }
+
+    fn resolve_with_base(&self, context: &MergeContext<Value>) -> MergeOutcome<Value> {
+        let mut heads: Vec<&ResolveInput<Value>> = context.heads.iter().collect();
+        heads.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.cid.cmp(&b.cid)));
+        let values: Vec<Option<&Value>> = heads.iter().map(|head| Some(&head.payload)).collect();
+        let base = context.base.as_ref().map(|base| &base.payload);
+        MergeOutcome::Merged(self.merge_value(base, &values).unwrap_or(Value::Null))
+    }
+
+    fn name(&self) -> &str {
+        "json"
+    }
+}
+
+fn union_arrays(base: Option<&Value>, changed: &[Option<&Value>]) -> Vec<Value> {
+    let base: &[Value] = base.and_then(Value::as_array).map_or(&[], Vec::as_slice);
+    let changed: Vec<&Vec<Value>> = changed
+        .iter()
+        .flatten()
+        .filter_map(|v| v.as_array())
+        .collect();
+
+    let mut merged: Vec<Value> = Vec::new();
+    for item in base.iter().chain(changed.iter().copied().flatten()) {
+        let removed = base.contains(item) && changed.iter().any(|array| !array.contains(item));
+        if !removed && !merged.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::Cid;
+    use multihash::Multihash;
+    use serde_json::json;
+
+    fn input(label: &str, payload: Value, timestamp: u64) -> ResolveInput<Value> {
+        let digest = Multihash::<64>::wrap(0x12, label.as_bytes()).unwrap();
+        ResolveInput::new(Cid::new_v1(0x55, digest), payload, timestamp)
+    }
+
+    #[test]
+    fn keeps_concurrent_edits_to_different_fields() {
+        let base = input(
+            "base",
+            json!({"title": "draft", "meta": {"a": 1, "b": 2}}),
+            1,
+        );
+        let left = input(
+            "left",
+            json!({"title": "final", "meta": {"a": 1, "b": 2}}),
+            2,
+        );
+        let right = input(
+            "right",
+            json!({"title": "draft", "meta": {"a": 1, "b": 3}}),
+            3,
+        );
+
+        let context = MergeContext::new(vec![right, left], Some(base));
+        assert_eq!(
+            JsonMergePolicy::new()
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            json!({"title": "final", "meta": {"a": 1, "b": 3}})
+        );
+    }
+
+    #[test]
+    fn same_field_uses_latest_timestamp() {
+        let base = input("base", json!({"title": "a", "body": "x"}), 1);
+        let newer = input("newer", json!({"title": "c", "body": "x"}), 5);
+        let older = input("older", json!({"title": "b", "body": "y"}), 4);
+
+        let context = MergeContext::new(vec![newer, older], Some(base));
+        assert_eq!(
+            JsonMergePolicy::new()
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            json!({"title": "c", "body": "y"})
+        );
+    }
+
+    #[test]
+    fn deletions_and_type_changes_are_leaf_changes() {
+        let base = input("base", json!({"keep": 1, "drop": 2, "shape": {"x": 1}}), 1);
+        let left = input("left", json!({"keep": 1, "shape": {"x": 1}}), 2);
+        let right = input("right", json!({"keep": 1, "drop": 2, "shape": "flat"}), 3);
+
+        let context = MergeContext::new(vec![left, right], Some(base));
+        assert_eq!(
+            JsonMergePolicy::new()
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            json!({"keep": 1, "shape": "flat"})
+        );
+    }
+
+    #[test]
+    fn arrays_replace_or_union() {
+        let base = input("base", json!({"tags": ["a", "b"]}), 1);
+        let left = input("left", json!({"tags": ["a", "b", "c"]}), 2);
+        let right = input("right", json!({"tags": ["b", "d"]}), 3);
+        let context = MergeContext::new(vec![left, right], Some(base));
+
+        assert_eq!(
+            JsonMergePolicy::new()
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            json!({"tags": ["b", "d"]})
+        );
+        assert_eq!(
+            JsonMergePolicy::new()
+                .with_arrays(ArrayMerge::Union)
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            json!({"tags": ["b", "c", "d"]})
+        );
+    }
+
+    #[test]
+    fn without_base_merges_union_of_keys() {
+        let older = input("older", json!({"a": 1, "shared": "old"}), 1);
+        let newer = input("newer", json!({"b": 2, "shared": "new"}), 2);
+
+        assert_eq!(
+            JsonMergePolicy::new()
+                .resolve(&[newer, older])
+                .merged()
+                .unwrap(),
+            json!({"a": 1, "b": 2, "shared": "new"})
+        );
+    }
+}
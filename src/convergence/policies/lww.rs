@@ -1,4 +1,4 @@
-use crate::convergence::policy::{MergePolicy, ResolveInput};
+use crate::convergence::policy::{MergeOutcome, MergePolicy, ResolveInput};
 
 /// A simple last-write-wins merge policy that selects the node with the
 /// greatest timestamp. Ties fall back to the last node in the slice, mimicking
@@ -7,12 +7,12 @@ use crate::convergence::policy::{MergePolicy, ResolveInput};
 pub struct LwwMergePolicy;
 
 impl<P: Clone> MergePolicy<P> for LwwMergePolicy {
-    fn resolve(&self, nodes: &[ResolveInput<P>]) -> P {
+    fn resolve(&self, nodes: &[ResolveInput<P>]) -> MergeOutcome<P> {
         let winner = nodes
             .iter()
             .max_by_key(|input| input.timestamp)
             .expect("LwwMergePolicy requires at least one candidate node");
-        winner.payload.clone()
+        MergeOutcome::Merged(winner.payload.clone())
     }
 
     fn name(&self) -> &str {
@@ -41,7 +41,7 @@ mod tests {
         ];
 
         let result = policy.resolve(&inputs);
-        assert_eq!(result, "newer");
+        assert_eq!(result, MergeOutcome::Merged("newer".to_string()));
     }
 
     #[test]
@@ -53,7 +53,7 @@ mod tests {
         ];
 
         let result = policy.resolve(&inputs);
-        assert_eq!(result, "second");
+        assert_eq!(result, MergeOutcome::Merged("second".to_string()));
     }
 
     #[test]
@@ -66,6 +66,6 @@ mod tests {
         ];
 
         let result = policy.resolve(&inputs);
-        assert_eq!(result, "payload-b");
+        assert_eq!(result, MergeOutcome::Merged("payload-b".to_string()));
     }
 }
@@ -1 +1,4 @@
+pub mod json;
 pub mod lww;
+pub mod text;
+pub mod three_way;
@@ -0,0 +1,365 @@
+use crate::convergence::diff::matching_pairs;
+use crate::convergence::policies::lww::LwwMergePolicy;
+use crate::convergence::policy::{MergeContext, MergeOutcome, MergePolicy, ResolveInput};
+use std::ops::Range;
+
+/// A region of a three-way text merge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeChunk {
+    /// Lines both sides agree on, or that only one side changed.
+    Resolved(String),
+    /// Lines both sides changed differently.
+    Conflict {
+        ours: String,
+        base: String,
+        theirs: String,
+    },
+}
+
+/// Result of a diff3-style line merge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextMerge {
+    pub chunks: Vec<MergeChunk>,
+}
+
+impl TextMerge {
+    /// Merges the line changes `ours` and `theirs` made to `base`.
+    ///
+    /// Changes to separate regions of `base` are combined. Changes that overlap or
+    /// touch are a conflict unless both sides produced the same lines.
+    pub fn merge(base: &str, ours: &str, theirs: &str) -> Self {
+        let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+        let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+        let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+        let mut hunks: Vec<(Side, Hunk)> = line_hunks(&base_lines, &our_lines)
+            .into_iter()
+            .map(|hunk| (Side::Ours, hunk))
+            .chain(
+                line_hunks(&base_lines, &their_lines)
+                    .into_iter()
+                    .map(|hunk| (Side::Theirs, hunk)),
+            )
+            .collect();
+        hunks.sort_by_key(|(side, hunk)| (hunk.base.start, hunk.base.end, *side));
+
+        let mut chunks = Vec::new();
+        let mut resolved = String::new();
+        let mut cursor = 0;
+        let mut index = 0;
+        while index < hunks.len() {
+            // Group every hunk that overlaps or touches the current region.
+            let start = hunks[index].1.base.start;
+            let mut end = hunks[index].1.base.end;
+            let mut group = index + 1;
+            while group < hunks.len() && hunks[group].1.base.start <= end {
+                end = end.max(hunks[group].1.base.end);
+                group += 1;
+            }
+            let region = &hunks[index..group];
+            index = group;
+
+            resolved.push_str(&base_lines[cursor..start].concat());
+            cursor = end;
+
+            let base_text = base_lines[start..end].concat();
+            let ours_text = side_text(region, Side::Ours, start..end, &our_lines);
+            let theirs_text = side_text(region, Side::Theirs, start..end, &their_lines);
+            match (ours_text, theirs_text) {
+                (Some(text), None) | (None, Some(text)) => resolved.push_str(&text),
+                (Some(ours), Some(theirs)) if ours == theirs => resolved.push_str(&ours),
+                (Some(ours), Some(theirs)) => {
+                    if !resolved.is_empty() {
+                        chunks.push(MergeChunk::Resolved(std::mem::take(&mut resolved)));
+                    }
+                    chunks.push(MergeChunk::Conflict {
+                        ours,
+                        base: base_text,
+                        theirs,
+                    });
+                }
+                (None, None) => resolved.push_str(&base_text),
+            }
+        }
+        resolved.push_str(&base_lines[cursor..].concat());
+        if !resolved.is_empty() {
+            chunks.push(MergeChunk::Resolved(resolved));
+        }
+
+        Self { chunks }
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.chunks
+            .iter()
+            .any(|chunk| matches!(chunk, MergeChunk::Conflict { .. }))
+    }
+
+    /// Renders the merge as text, wrapping conflicts in diff3-style markers.
+    pub fn render(&self, ours_label: &str, theirs_label: &str) -> String {
+        let mut text = String::new();
+        for chunk in &self.chunks {
+            match chunk {
+                MergeChunk::Resolved(lines) => text.push_str(lines),
+                MergeChunk::Conflict { ours, base, theirs } => {
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("<<<<<<< {ours_label}\n"));
+                    push_block(&mut text, ours);
+                    text.push_str("||||||| base\n");
+                    push_block(&mut text, base);
+                    text.push_str("=======\n");
+                    push_block(&mut text, theirs);
+                    text.push_str(&format!(">>>>>>> {theirs_label}\n"));
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Line-based three-way merge policy for text payloads.
+///
+/// Heads are merged into each other against the common ancestor in timestamp order
+/// (ties broken by CID), so the result does not depend on the order the heads are
+/// given in. Overlapping edits are kept side by side between conflict markers
+/// labelled with the head CIDs, or reported as a conflict when created with
+/// [`TextMergePolicy::manual`]. Without a common ancestor it falls back to
+/// [`LwwMergePolicy`].
+#[derive(Debug, Default)]
+pub struct TextMergePolicy {
+    manual: bool,
+}
+
+impl TextMergePolicy {
+    /// Creates the policy that writes conflict markers (`"text"`).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the policy that leaves overlapping edits for manual resolution
+    /// (`"text-manual"`).
+    pub fn manual() -> Self {
+        Self { manual: true }
+    }
+}
+
+impl MergePolicy<String> for TextMergePolicy {
+    fn resolve(&self, nodes: &[ResolveInput<String>]) -> MergeOutcome<String> {
+        LwwMergePolicy.resolve(nodes)
+    }
+
+    fn resolve_with_base(&self, context: &MergeContext<String>) -> MergeOutcome<String> {
+        let Some(base) = &context.base else {
+            return self.resolve(&context.heads);
+        };
+
+        let mut heads: Vec<&ResolveInput<String>> = context.heads.iter().collect();
+        heads.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.cid.cmp(&b.cid)));
+        let Some((first, rest)) = heads.split_first() else {
+            return MergeOutcome::Merged(base.payload.clone());
+        };
+
+        let mut merged = first.payload.clone();
+        let mut label = first.cid.to_string();
+        for head in rest {
+            let merge = TextMerge::merge(&base.payload, &merged, &head.payload);
+            if self.manual && merge.has_conflicts() {
+                return MergeOutcome::conflict(
+                    context.heads.clone(),
+                    "concurrent edits overlap on the same lines",
+                );
+            }
+            let theirs = head.cid.to_string();
+            merged = merge.render(&label, &theirs);
+            label = format!("{label}+{theirs}");
+        }
+        MergeOutcome::Merged(merged)
+    }
+
+    fn name(&self) -> &str {
+        if self.manual {
+            "text-manual"
+        } else {
+            "text"
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+/// A replaced range of base lines and the side lines that replace it.
+#[derive(Debug)]
+struct Hunk {
+    base: Range<usize>,
+    side: Range<usize>,
+}
+
+fn line_hunks(base: &[&str], side: &[&str]) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let (mut base_pos, mut side_pos) = (0, 0);
+    let sentinel = (base.len(), side.len());
+    for (base_index, side_index) in matching_pairs(base, side).into_iter().chain([sentinel]) {
+        if base_index > base_pos || side_index > side_pos {
+            hunks.push(Hunk {
+                base: base_pos..base_index,
+                side: side_pos..side_index,
+            });
+        }
+        base_pos = base_index + 1;
+        side_pos = side_index + 1;
+    }
+    hunks
+}
+
+/// Returns the lines a side has in place of `region`, or `None` if it left it alone.
+fn side_text(
+    hunks: &[(Side, Hunk)],
+    side: Side,
+    region: Range<usize>,
+    lines: &[&str],
+) -> Option<String> {
+    let mut own = hunks.iter().filter(|(owner, _)| *owner == side);
+    let (_, first) = own.next()?;
+    let last = own.next_back().map_or(first, |(_, hunk)| hunk);
+    // Outside its hunks the side matches the base line for line.
+    let start = first.side.start - (first.base.start - region.start);
+    let end = last.side.end + (region.end - last.base.end);
+    Some(lines[start..end].concat())
+}
+
+fn push_block(text: &mut String, block: &str) {
+    text.push_str(block);
+    if !block.is_empty() && !block.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::Cid;
+    use multihash::Multihash;
+
+    fn create_test_cid(label: &str) -> Cid {
+        let digest = Multihash::<64>::wrap(0x12, label.as_bytes()).unwrap();
+        Cid::new_v1(0x55, digest)
+    }
+
+    #[test]
+    fn merges_edits_to_separate_lines() {
+        let base = "title\nintro\nbody\nfooter\n";
+        let ours = "Title\nintro\nbody\nfooter\n";
+        let theirs = "title\nintro\nbody\nfooter\nappendix\n";
+
+        let merge = TextMerge::merge(base, ours, theirs);
+        assert!(!merge.has_conflicts());
+        assert_eq!(
+            merge.render("a", "b"),
+            "Title\nintro\nbody\nfooter\nappendix\n"
+        );
+    }
+
+    #[test]
+    fn identical_edits_do_not_conflict() {
+        let merge = TextMerge::merge("a\nb\n", "a\nB\n", "a\nB\n");
+        assert_eq!(merge.chunks, vec![MergeChunk::Resolved("a\nB\n".into())]);
+    }
+
+    #[test]
+    fn overlapping_edits_produce_conflict() {
+        let merge = TextMerge::merge("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+        assert!(merge.has_conflicts());
+        assert_eq!(
+            merge.chunks,
+            vec![
+                MergeChunk::Resolved("a\n".into()),
+                MergeChunk::Conflict {
+                    ours: "ours\n".into(),
+                    base: "b\n".into(),
+                    theirs: "theirs\n".into(),
+                },
+                MergeChunk::Resolved("c\n".into()),
+            ]
+        );
+        assert_eq!(
+            merge.render("left", "right"),
+            "a\n<<<<<<< left\nours\n||||||| base\nb\n=======\ntheirs\n>>>>>>> right\nc\n"
+        );
+    }
+
+    #[test]
+    fn edit_against_deletion_conflicts() {
+        let merge = TextMerge::merge("a\nb\nc\n", "a\nc\n", "a\nB\nc\n");
+        assert_eq!(
+            merge.chunks[1],
+            MergeChunk::Conflict {
+                ours: String::new(),
+                base: "b\n".into(),
+                theirs: "B\n".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn policy_is_independent_of_head_order() {
+        let base = ResolveInput::new(create_test_cid("base"), "one\ntwo\nthree\n".into(), 1);
+        let left = ResolveInput::new(create_test_cid("left"), "ONE\ntwo\nthree\n".into(), 2);
+        let right = ResolveInput::new(create_test_cid("right"), "one\ntwo\nTHREE\n".into(), 3);
+
+        let forward = MergeContext::new(vec![left.clone(), right.clone()], Some(base.clone()));
+        let backward = MergeContext::new(vec![right, left], Some(base));
+        let merged = TextMergePolicy::new().resolve_with_base(&forward);
+        assert_eq!(
+            merged,
+            MergeOutcome::Merged("ONE\ntwo\nTHREE\n".to_string())
+        );
+        assert_eq!(TextMergePolicy::new().resolve_with_base(&backward), merged);
+    }
+
+    #[test]
+    fn policy_labels_conflicts_with_head_cids() {
+        let base = ResolveInput::new(create_test_cid("base"), "x\n".into(), 1);
+        let newer = ResolveInput::new(create_test_cid("newer"), "new\n".into(), 3);
+        let older = ResolveInput::new(create_test_cid("older"), "old\n".into(), 2);
+
+        let context = MergeContext::new(vec![newer.clone(), older.clone()], Some(base));
+        let merged = TextMergePolicy::new().resolve_with_base(&context);
+        assert_eq!(
+            merged.merged().unwrap(),
+            format!(
+                "<<<<<<< {}\nold\n||||||| base\nx\n=======\nnew\n>>>>>>> {}\n",
+                older.cid, newer.cid
+            )
+        );
+    }
+
+    #[test]
+    fn manual_policy_reports_overlapping_edits() {
+        let base = ResolveInput::new(create_test_cid("base"), "x\ny\n".into(), 1);
+        let left = ResolveInput::new(create_test_cid("left"), "left\ny\n".into(), 2);
+        let right = ResolveInput::new(create_test_cid("right"), "right\ny\n".into(), 3);
+        let policy = TextMergePolicy::manual();
+        assert_eq!(policy.name(), "text-manual");
+
+        let context = MergeContext::new(vec![left.clone(), right.clone()], Some(base.clone()));
+        match policy.resolve_with_base(&context) {
+            MergeOutcome::Conflict(conflict) => {
+                assert_eq!(conflict.candidates, vec![left, right]);
+            }
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+
+        let clean = ResolveInput::new(create_test_cid("clean"), "x\nY\n".into(), 4);
+        let context = MergeContext::new(vec![clean], Some(base));
+        assert_eq!(
+            policy.resolve_with_base(&context),
+            MergeOutcome::Merged("x\nY\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::convergence::diff::Diffable;
+use crate::convergence::policies::lww::LwwMergePolicy;
+use crate::convergence::policy::{MergeContext, MergeOutcome, MergePolicy, ResolveInput};
+
+/// Three-way merge policy for [`Diffable`] payloads.
+///
+/// Each head's changes relative to the common ancestor are applied to the ancestor in
+/// timestamp order (ties broken by CID), so later heads win where patches overlap and
+/// the result does not depend on the order the heads are given in. When a patch no
+/// longer fits, as when two heads edit the same line of a text (see
+/// [`Diffable::try_apply`]), the merge is reported as a conflict. Without a common
+/// ancestor it falls back to [`LwwMergePolicy`].
+#[derive(Debug, Default)]
+pub struct ThreeWayMergePolicy;
+
+impl<P> MergePolicy<P> for ThreeWayMergePolicy
+where
+    P: Diffable + Clone,
+{
+    fn resolve(&self, nodes: &[ResolveInput<P>]) -> MergeOutcome<P> {
+        LwwMergePolicy.resolve(nodes)
+    }
+
+    fn resolve_with_base(&self, context: &MergeContext<P>) -> MergeOutcome<P> {
+        let Some(base) = &context.base else {
+            return self.resolve(&context.heads);
+        };
+
+        let mut heads: Vec<&ResolveInput<P>> = context.heads.iter().collect();
+        heads.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.cid.cmp(&b.cid)));
+        let mut merged = base.payload.clone();
+        for head in heads {
+            match merged.try_apply(&base.payload.diff(&head.payload)) {
+                Some(next) => merged = next,
+                None => {
+                    return MergeOutcome::conflict(
+                        context.heads.clone(),
+                        format!("changes of {} overlap with another head", head.cid),
+                    )
+                }
+            }
+        }
+        MergeOutcome::Merged(merged)
+    }
+
+    fn name(&self) -> &str {
+        "three-way"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::Cid;
+    use multihash::Multihash;
+    use std::collections::BTreeMap;
+
+    /// Key/value document whose patch lists the keys set or removed.
+    #[derive(Clone, Debug, PartialEq)]
+    struct Fields(BTreeMap<String, String>);
+
+    impl Diffable for Fields {
+        type Patch = Vec<(String, Option<String>)>;
+
+        fn diff(&self, other: &Self) -> Self::Patch {
+            let mut patch: Self::Patch = other
+                .0
+                .iter()
+                .filter(|(key, value)| self.0.get(*key) != Some(*value))
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect();
+            patch.extend(
+                self.0
+                    .keys()
+                    .filter(|key| !other.0.contains_key(*key))
+                    .map(|key| (key.clone(), None)),
+            );
+            patch
+        }
+
+        fn apply(&self, patch: &Self::Patch) -> Self {
+            let mut fields = self.0.clone();
+            for (key, value) in patch {
+                match value {
+                    Some(value) => fields.insert(key.clone(), value.clone()),
+                    None => fields.remove(key),
+                };
+            }
+            Fields(fields)
+        }
+    }
+
+    fn fields(entries: &[(&str, &str)]) -> Fields {
+        Fields(
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    fn create_test_cid(label: &str) -> Cid {
+        let digest = Multihash::<64>::wrap(0x12, label.as_bytes()).unwrap();
+        Cid::new_v1(0x55, digest)
+    }
+
+    #[test]
+    fn keeps_changes_from_every_head() {
+        let base = ResolveInput::new(
+            create_test_cid("base"),
+            fields(&[("title", "draft"), ("body", "text"), ("tag", "x")]),
+            1,
+        );
+        let left = ResolveInput::new(
+            create_test_cid("left"),
+            fields(&[("title", "final"), ("body", "text"), ("tag", "x")]),
+            2,
+        );
+        let right = ResolveInput::new(
+            create_test_cid("right"),
+            fields(&[("title", "draft"), ("body", "edited")]),
+            3,
+        );
+
+        let expected = fields(&[("title", "final"), ("body", "edited")]);
+        let context = MergeContext::new(vec![left.clone(), right.clone()], Some(base.clone()));
+        assert_eq!(
+            ThreeWayMergePolicy
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            expected
+        );
+
+        let reversed = MergeContext::new(vec![right, left], Some(base));
+        assert_eq!(
+            ThreeWayMergePolicy.resolve_with_base(&reversed).merged(),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn overlapping_changes_prefer_later_head() {
+        let base = ResolveInput::new(create_test_cid("base"), fields(&[("title", "a")]), 1);
+        let older = ResolveInput::new(create_test_cid("older"), fields(&[("title", "b")]), 2);
+        let newer = ResolveInput::new(create_test_cid("newer"), fields(&[("title", "c")]), 3);
+
+        let context = MergeContext::new(vec![newer, older], Some(base));
+        assert_eq!(
+            ThreeWayMergePolicy
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            fields(&[("title", "c")])
+        );
+    }
+
+    #[test]
+    fn same_line_edits_conflict() {
+        let base = ResolveInput::new(create_test_cid("base"), "a\nb\nc\n".to_string(), 1);
+        let older = ResolveInput::new(create_test_cid("older"), "a\nB\nc\n".to_string(), 2);
+        let newer = ResolveInput::new(create_test_cid("newer"), "a\nbee\nc\n".to_string(), 3);
+
+        let context = MergeContext::new(vec![newer.clone(), older.clone()], Some(base.clone()));
+        match ThreeWayMergePolicy.resolve_with_base(&context) {
+            MergeOutcome::Conflict(conflict) => {
+                assert_eq!(conflict.candidates, vec![newer.clone(), older.clone()]);
+            }
+            MergeOutcome::Merged(text) => panic!("expected a conflict, merged {text:?}"),
+        }
+
+        let separate = ResolveInput::new(create_test_cid("sep"), "a\nb\nC\n".to_string(), 3);
+        let context = MergeContext::new(vec![separate, older], Some(base));
+        assert_eq!(
+            ThreeWayMergePolicy.resolve_with_base(&context).merged(),
+            Some("a\nB\nC\n".to_string())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_lww_without_base() {
+        let older = ResolveInput::new(create_test_cid("older"), fields(&[("a", "1")]), 2);
+        let newer = ResolveInput::new(create_test_cid("newer"), fields(&[("b", "2")]), 3);
+
+        let context = MergeContext::new(vec![newer, older], None);
+        assert_eq!(
+            ThreeWayMergePolicy
+                .resolve_with_base(&context)
+                .merged()
+                .unwrap(),
+            fields(&[("b", "2")])
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use cid::Cid;
 
 /// Unites metadata about a DAG node that should be considered during merge resolution.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResolveInput<P> {
     pub cid: Cid,
     pub payload: P,
@@ -18,10 +18,88 @@ impl<P> ResolveInput<P> {
     }
 }
 
+/// Everything a policy can see when merging heads: the heads themselves and the
+/// version they diverged from.
+#[derive(Clone, Debug)]
+pub struct MergeContext<P> {
+    /// The heads to merge, in the order they were given to the resolver.
+    pub heads: Vec<ResolveInput<P>>,
+    /// The lowest common ancestor of the heads.
+    ///
+    /// When several exist (criss-cross merges) the one with the greatest timestamp is
+    /// used, ties broken by CID. `None` when the heads share no history.
+    pub base: Option<ResolveInput<P>>,
+    /// CIDs of all lowest common ancestors, sorted.
+    pub ancestors: Vec<Cid>,
+}
+
+impl<P> MergeContext<P> {
+    /// Creates a context whose only known common ancestor is `base`; use
+    /// [`Self::with_ancestors`] when the heads have several.
+    pub fn new(heads: Vec<ResolveInput<P>>, base: Option<ResolveInput<P>>) -> Self {
+        let ancestors = base.iter().map(|input| input.cid).collect();
+        Self {
+            heads,
+            base,
+            ancestors,
+        }
+    }
+
+    /// Replaces the lowest common ancestors, e.g. with every one of a criss-cross merge.
+    pub fn with_ancestors(mut self, mut ancestors: Vec<Cid>) -> Self {
+        ancestors.sort();
+        self.ancestors = ancestors;
+        self
+    }
+}
+
+/// Candidates a policy could not merge on its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeConflict<P> {
+    /// The competing nodes, typically all heads.
+    pub candidates: Vec<ResolveInput<P>>,
+    /// Why the policy gave up, for display to the user.
+    pub reason: String,
+}
+
+/// Result of running a [`MergePolicy`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MergeOutcome<P> {
+    /// The heads converged into this payload.
+    Merged(P),
+    /// The heads need manual resolution.
+    Conflict(MergeConflict<P>),
+}
+
+impl<P> MergeOutcome<P> {
+    /// Creates a conflict outcome.
+    pub fn conflict(candidates: Vec<ResolveInput<P>>, reason: impl Into<String>) -> Self {
+        MergeOutcome::Conflict(MergeConflict {
+            candidates,
+            reason: reason.into(),
+        })
+    }
+
+    /// Returns the merged payload, or `None` for a conflict.
+    pub fn merged(self) -> Option<P> {
+        match self {
+            MergeOutcome::Merged(payload) => Some(payload),
+            MergeOutcome::Conflict(_) => None,
+        }
+    }
+}
+
 /// A merge strategy that produces a converged payload from candidate nodes.
 pub trait MergePolicy<P>: Send + Sync {
-    /// Resolve competing nodes into a single payload.
-    fn resolve(&self, nodes: &[ResolveInput<P>]) -> P;
+    /// Resolve competing nodes into a single payload, or report a conflict.
+    fn resolve(&self, nodes: &[ResolveInput<P>]) -> MergeOutcome<P>;
+
+    /// Resolve competing nodes with knowledge of their common ancestor.
+    ///
+    /// Defaults to [`Self::resolve`] on the heads, ignoring the base.
+    fn resolve_with_base(&self, context: &MergeContext<P>) -> MergeOutcome<P> {
+        self.resolve(&context.heads)
+    }
 
     /// Return a descriptive name of the policy (e.g. "lww").
     fn name(&self) -> &str;
@@ -0,0 +1,95 @@
+use crate::convergence::policies::lww::LwwMergePolicy;
+use crate::convergence::policy::MergePolicy;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Merge policies available to a repository, keyed by the name stored in
+/// `ContentMetadata::policy_type`.
+pub struct PolicyRegistry<P> {
+    policies: HashMap<String, Arc<dyn MergePolicy<P>>>,
+}
+
+impl<P> PolicyRegistry<P> {
+    /// Creates a registry without any policies.
+    pub fn empty() -> Self {
+        Self {
+            policies: HashMap::new(),
+        }
+    }
+
+    /// Registers a policy under [`MergePolicy::name`], replacing and returning any
+    /// policy previously registered under that name.
+    pub fn register(&mut self, policy: Box<dyn MergePolicy<P>>) -> Option<Arc<dyn MergePolicy<P>>> {
+        let name = policy.name().to_string();
+        self.policies.insert(name, Arc::from(policy))
+    }
+
+    /// Looks up a policy by name.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn MergePolicy<P>>> {
+        self.policies.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.policies.contains_key(name)
+    }
+
+    /// Returns the registered policy names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.policies.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl<P: Clone> PolicyRegistry<P> {
+    /// Creates a registry with the built-in policies (currently `"lww"`).
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Box::new(LwwMergePolicy));
+        registry
+    }
+}
+
+impl<P: Clone> Default for PolicyRegistry<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::policy::{MergeOutcome, ResolveInput};
+
+    struct FirstWins;
+
+    impl MergePolicy<String> for FirstWins {
+        fn resolve(&self, nodes: &[ResolveInput<String>]) -> MergeOutcome<String> {
+            MergeOutcome::Merged(nodes[0].payload.clone())
+        }
+
+        fn name(&self) -> &str {
+            "first-wins"
+        }
+    }
+
+    #[test]
+    fn new_registry_contains_builtin_lww() {
+        let registry = PolicyRegistry::<String>::new();
+        assert!(registry.contains("lww"));
+        assert_eq!(registry.names(), vec!["lww"]);
+        assert!(PolicyRegistry::<String>::empty().names().is_empty());
+    }
+
+    #[test]
+    fn register_and_replace_by_name() {
+        let mut registry = PolicyRegistry::<String>::new();
+        assert!(registry.register(Box::new(FirstWins)).is_none());
+        assert_eq!(registry.names(), vec!["first-wins", "lww"]);
+        assert_eq!(registry.get("first-wins").unwrap().name(), "first-wins");
+        assert!(registry.get("missing").is_none());
+
+        let replaced = registry.register(Box::new(FirstWins));
+        assert_eq!(replaced.unwrap().name(), "first-wins");
+    }
+}
@@ -1,5 +1,5 @@
-use crate::convergence::policy::{MergePolicy, ResolveInput};
-use crate::crdt::error::{CrdtError, Result as CrdtResult};
+use crate::convergence::policy::{MergeContext, MergeOutcome, MergePolicy, ResolveInput};
+use crate::crdt::error::{CrdtError, Result as CrdtResult, UnresolvedMerge};
 use crate::dasl::node::Node;
 use crate::graph::dag::DagGraph;
 use crate::graph::storage::NodeStorage;
@@ -26,6 +26,8 @@ where
 
     /// Creates a merge node from the given heads.
     ///
+    /// Returns [`CrdtError::MergeConflict`] when the policy asks for manual resolution.
+    ///
     /// # Arguments
     ///
     /// * `heads` - The head CIDs to merge
@@ -52,8 +54,21 @@ where
             ));
         }
 
-        let inputs = self.collect_inputs(heads, dag)?;
-        let merged_payload = policy.resolve(&inputs);
+        let context = self.merge_context(heads, dag)?;
+        let merged_payload = match policy.resolve_with_base(&context) {
+            MergeOutcome::Merged(payload) => payload,
+            MergeOutcome::Conflict(conflict) => {
+                return Err(CrdtError::MergeConflict(Box::new(UnresolvedMerge {
+                    genesis,
+                    candidates: conflict
+                        .candidates
+                        .iter()
+                        .map(|candidate| candidate.cid)
+                        .collect(),
+                    reason: conflict.reason,
+                })));
+            }
+        };
         let metadata = self.merge_metadata(heads, dag)?;
         Ok(Node::new_child(
             merged_payload,
@@ -64,6 +79,29 @@ where
         ))
     }
 
+    /// Collects the heads and their lowest common ancestor for a merge policy.
+    ///
+    /// See [`MergeContext::base`] for how a single base is chosen when the heads have
+    /// several lowest common ancestors.
+    pub fn merge_context<S>(
+        &self,
+        heads: &[Cid],
+        dag: &DagGraph<S, P, M>,
+    ) -> CrdtResult<MergeContext<P>>
+    where
+        S: NodeStorage<P, M>,
+        P: serde::Serialize + for<'de> serde::Deserialize<'de>,
+        M: serde::Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let inputs = self.collect_inputs(heads, dag)?;
+        let ancestors = dag.common_ancestors(heads).map_err(CrdtError::Graph)?;
+        let mut candidates = self.collect_inputs(&ancestors, dag)?;
+        candidates.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.cid.cmp(&b.cid)));
+        let base = candidates.pop();
+
+        Ok(MergeContext::new(inputs, base).with_ancestors(ancestors))
+    }
+
     fn collect_inputs<S>(
         &self,
         heads: &[Cid],
@@ -111,7 +149,7 @@ mod tests {
     use super::*;
     use crate::convergence::metadata::ContentMetadata;
     use crate::convergence::policies::lww::LwwMergePolicy;
-    use crate::convergence::policy::{MergePolicy, ResolveInput};
+    use crate::convergence::policy::{MergeContext, MergeOutcome, MergePolicy, ResolveInput};
     use crate::crdt::error::CrdtError;
     use crate::dasl::node::Node;
     use crate::graph::error::{GraphError, Result as GraphResult};
@@ -173,14 +211,14 @@ mod tests {
     }
 
     impl MergePolicy<String> for AssertingPolicy {
-        fn resolve(&self, nodes: &[ResolveInput<String>]) -> String {
+        fn resolve(&self, nodes: &[ResolveInput<String>]) -> MergeOutcome<String> {
             assert_eq!(nodes.len(), self.expected.len());
             for (input, expected) in nodes.iter().zip(&self.expected) {
                 assert_eq!(input.cid, expected.0);
                 assert_eq!(input.payload, expected.1);
                 assert_eq!(input.timestamp, expected.2);
             }
-            self.result.clone()
+            MergeOutcome::Merged(self.result.clone())
         }
 
         fn name(&self) -> &str {
@@ -250,6 +288,143 @@ mod tests {
         assert_eq!(merge_node.timestamp(), merge_timestamp);
     }
 
+    struct BaseEchoPolicy;
+
+    impl MergePolicy<String> for BaseEchoPolicy {
+        fn resolve(&self, _nodes: &[ResolveInput<String>]) -> MergeOutcome<String> {
+            unreachable!("resolver must call resolve_with_base")
+        }
+
+        fn resolve_with_base(&self, context: &MergeContext<String>) -> MergeOutcome<String> {
+            MergeOutcome::Merged(
+                context
+                    .base
+                    .as_ref()
+                    .map(|base| base.payload.clone())
+                    .unwrap_or_default(),
+            )
+        }
+
+        fn name(&self) -> &str {
+            "base-echo"
+        }
+    }
+
+    #[test]
+    fn merge_context_exposes_common_ancestor() {
+        let storage = MemoryNodeStorage::<String, ContentMetadata>::default();
+        let dag = DagGraph::new(storage.clone());
+        let metadata = ContentMetadata::default();
+
+        let genesis_node = Node::new_genesis("genesis".to_string(), 1, metadata.clone());
+        let genesis_cid = genesis_node.content_id().unwrap();
+        dag.storage.put(&genesis_node).unwrap();
+        let base = Node::new_child(
+            "base".to_string(),
+            vec![genesis_cid],
+            genesis_cid,
+            2,
+            metadata.clone(),
+        );
+        let base_cid = base.content_id().unwrap();
+        dag.storage.put(&base).unwrap();
+
+        let mut heads = Vec::new();
+        for (label, timestamp) in [("left", 3), ("right", 4)] {
+            let head = Node::new_child(
+                label.to_string(),
+                vec![base_cid],
+                genesis_cid,
+                timestamp,
+                metadata.clone(),
+            );
+            heads.push(head.content_id().unwrap());
+            dag.storage.put(&head).unwrap();
+        }
+
+        let resolver = ConflictResolver::<String, ContentMetadata>::new();
+        let context = resolver.merge_context(&heads, &dag).unwrap();
+        assert_eq!(context.ancestors, vec![base_cid]);
+        let context_base = context.base.as_ref().unwrap();
+        assert_eq!(context_base.cid, base_cid);
+        assert_eq!(context_base.payload, "base");
+        assert_eq!(context_base.timestamp, 2);
+        assert_eq!(context.heads.len(), 2);
+
+        let merge_node = resolver
+            .create_merge_node(&heads, &dag, genesis_cid, 10, &BaseEchoPolicy)
+            .unwrap();
+        assert_eq!(merge_node.payload(), "base");
+    }
+
+    #[test]
+    fn merge_context_lists_every_criss_cross_ancestor() {
+        let dag = DagGraph::new(MemoryNodeStorage::<String, ContentMetadata>::default());
+        let metadata = ContentMetadata::default();
+
+        let genesis_node = Node::new_genesis("genesis".to_string(), 1, metadata.clone());
+        let genesis_cid = genesis_node.content_id().unwrap();
+        dag.storage.put(&genesis_node).unwrap();
+
+        let put_child = |payload: &str, parents: Vec<Cid>, timestamp| {
+            let node = Node::new_child(
+                payload.to_string(),
+                parents,
+                genesis_cid,
+                timestamp,
+                metadata.clone(),
+            );
+            dag.storage.put(&node).unwrap();
+            node.content_id().unwrap()
+        };
+        let left = put_child("left", vec![genesis_cid], 2);
+        let right = put_child("right", vec![genesis_cid], 3);
+        let heads = vec![
+            put_child("merge-a", vec![left, right], 4),
+            put_child("merge-b", vec![right, left], 5),
+        ];
+
+        let resolver = ConflictResolver::<String, ContentMetadata>::new();
+        let context = resolver.merge_context(&heads, &dag).unwrap();
+        let mut expected = vec![left, right];
+        expected.sort();
+        assert_eq!(context.ancestors, expected);
+        assert_eq!(context.base.unwrap().cid, right);
+    }
+
+    struct RefusingPolicy;
+
+    impl MergePolicy<String> for RefusingPolicy {
+        fn resolve(&self, nodes: &[ResolveInput<String>]) -> MergeOutcome<String> {
+            MergeOutcome::conflict(nodes.to_vec(), "refused")
+        }
+
+        fn name(&self) -> &str {
+            "refuse"
+        }
+    }
+
+    #[test]
+    fn create_merge_node_reports_policy_conflict() {
+        let dag = DagGraph::new(MemoryNodeStorage::<String, ContentMetadata>::default());
+        let genesis_node = Node::new_genesis("genesis".to_string(), 1, ContentMetadata::new());
+        let genesis_cid = genesis_node.content_id().unwrap();
+        dag.storage.put(&genesis_node).unwrap();
+
+        let resolver = ConflictResolver::<String, ContentMetadata>::new();
+        let result =
+            resolver.create_merge_node(&[genesis_cid], &dag, genesis_cid, 10, &RefusingPolicy);
+
+        match result {
+            Err(CrdtError::MergeConflict(conflict)) => {
+                assert_eq!(conflict.genesis, genesis_cid);
+                assert_eq!(conflict.candidates, vec![genesis_cid]);
+                assert_eq!(conflict.reason, "refused");
+            }
+            other => panic!("expected a merge conflict, got {other:?}"),
+        }
+    }
+
     #[test]
     fn create_merge_node_requires_non_empty_heads() {
         let dag = DagGraph::new(MemoryNodeStorage::<String, ContentMetadata>::default());
@@ -1,6 +1,6 @@
 use crate::crdt::error::{CrdtError, Result, ValidationError};
 use crate::crdt::operation::{Operation, OperationType};
-use crate::crdt::reducer::Reducer;
+use crate::crdt::reducer::{Reducer, StateSnapshot};
 use crate::crdt::storage::OperationStorage;
 use std::fmt::Debug;
 use std::marker::PhantomData;
@@ -53,8 +53,12 @@ where
     /// # Parameters
     ///
     /// * `op` - The operation to apply
+    ///
+    /// Incremental reducers also fold the operation into the genesis' state snapshot,
+    /// which is written in the same batch as the operation.
     pub fn apply(&self, op: Operation<ContentId, T>) -> Result<()> {
-        self.storage.save_operation(&op)
+        self.storage.save_operation(&op)?;
+        self.update_snapshot(&op)
     }
 
     /// Applies an operation to the CRDT state with validation.
@@ -77,7 +81,14 @@ where
             )))
         }
     }
+    /// Returns the current value of `genesis`.
+    ///
+    /// Reads the state snapshot when there is one, otherwise reduces all operations.
+    /// A snapshot that cannot be loaded is treated as missing.
     pub fn get_state(&self, genesis: &ContentId) -> Option<T> {
+        if let Ok(Some(snapshot)) = self.load_snapshot(genesis) {
+            return snapshot.value;
+        }
         let ops = self.storage.load_operations(genesis).ok()?;
         R::reduce(&ops)
     }
@@ -93,8 +104,62 @@ where
         self.storage.get_operation(op_id)
     }
 
+    /// Deletes an operation and rebuilds the state snapshot of its genesis.
     pub fn delete_operation(&self, op_id: &Ulid) -> Result<()> {
-        self.storage.delete_operation(op_id)
+        let Some(op) = self.storage.get_operation(op_id)? else {
+            return self.storage.delete_operation(op_id);
+        };
+        self.storage.delete_operation(op_id)?;
+        let mut ops = self.storage.load_operations(&op.genesis)?;
+        ops.retain(|other| other.id != *op_id);
+        match R::snapshot(&ops) {
+            Some(snapshot) => self.save_snapshot(&op.genesis, snapshot),
+            None => self.storage.delete_snapshot(&op.genesis),
+        }
+    }
+
+    /// Folds `op` into the state snapshot of its genesis.
+    ///
+    /// A missing snapshot is rebuilt from the operation log, which covers logs written
+    /// before snapshots were kept or by a different reducer. So is one that cannot be
+    /// loaded, which replaces it.
+    fn update_snapshot(&self, op: &Operation<ContentId, T>) -> Result<()> {
+        let snapshot = match self.load_snapshot(&op.genesis).unwrap_or(None) {
+            Some(previous) => R::fold(Some(previous), op),
+            None => match R::fold(None, op) {
+                Some(first) if matches!(op.kind, OperationType::Create(_)) => Some(first),
+                Some(_) => {
+                    let mut ops = self.storage.load_operations(&op.genesis)?;
+                    ops.retain(|other| other.id != op.id);
+                    ops.push(op.clone());
+                    R::snapshot(&ops)
+                }
+                // Not an incremental reducer.
+                None => None,
+            },
+        };
+        match snapshot {
+            Some(snapshot) => self.save_snapshot(&op.genesis, snapshot),
+            // Drop any snapshot another reducer left, as it no longer covers the log.
+            None => self.storage.delete_snapshot(&op.genesis),
+        }
+    }
+
+    /// Loads the snapshot of `genesis` if it was built by this reducer.
+    fn load_snapshot(&self, genesis: &ContentId) -> Result<Option<StateSnapshot<ContentId, T>>> {
+        Ok(self
+            .storage
+            .load_snapshot(genesis)?
+            .filter(|snapshot| snapshot.reducer() == R::NAME))
+    }
+
+    fn save_snapshot(
+        &self,
+        genesis: &ContentId,
+        snapshot: StateSnapshot<ContentId, T>,
+    ) -> Result<()> {
+        let snapshot = snapshot.with_reducer(R::NAME);
+        self.storage.save_snapshot(genesis, &snapshot)
     }
 
     /// Validates whether an operation is logically valid to apply.
@@ -330,4 +395,152 @@ mod tests {
             .iter()
             .any(|op| op.kind == OperationType::Update(DummyPayload("C".into()))));
     }
+
+    #[test]
+    fn test_apply_maintains_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage =
+            crate::crdt::storage::LeveldbStorage::<DummyContentId, DummyPayload>::open(dir.path())
+                .unwrap();
+        let state: CrdtState<DummyContentId, DummyPayload, _, LwwReducer> = CrdtState::new(storage);
+        let genesis = DummyContentId("1".to_string());
+        let op1 = make_op(1, 100, OperationType::Create(DummyPayload("A".to_string())));
+        let op2 = make_op(1, 300, OperationType::Update(DummyPayload("B".to_string())));
+        let op3 = make_op(1, 200, OperationType::Update(DummyPayload("C".to_string())));
+
+        state.apply(op1).unwrap();
+        state.apply(op2.clone()).unwrap();
+        state.apply(op3.clone()).unwrap();
+
+        let snapshot = state.storage().load_snapshot(&genesis).unwrap().unwrap();
+        assert_eq!(snapshot.value, Some(DummyPayload("B".to_string())));
+        assert_eq!(snapshot.heads, vec![op2.clone()]);
+        assert_eq!(snapshot.last_applied, op3.id);
+
+        state.delete_operation(&op2.id).unwrap();
+        assert_eq!(
+            state.get_state(&genesis),
+            Some(DummyPayload("C".to_string()))
+        );
+        let snapshot = state.storage().load_snapshot(&genesis).unwrap().unwrap();
+        assert_eq!(snapshot.heads, vec![op3]);
+    }
+
+    #[test]
+    fn test_missing_snapshot_is_rebuilt_from_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage =
+            crate::crdt::storage::LeveldbStorage::<DummyContentId, DummyPayload>::open(dir.path())
+                .unwrap();
+        let state: CrdtState<DummyContentId, DummyPayload, _, LwwReducer> = CrdtState::new(storage);
+        let genesis = DummyContentId("1".to_string());
+
+        // Operations written before snapshots were kept.
+        let create = make_op(1, 100, OperationType::Create(DummyPayload("A".to_string())));
+        let newest = make_op(1, 500, OperationType::Update(DummyPayload("B".to_string())));
+        state.storage().save_operation(&create).unwrap();
+        state.storage().save_operation(&newest).unwrap();
+        assert_eq!(state.storage().load_snapshot(&genesis).unwrap(), None);
+        assert_eq!(
+            state.get_state(&genesis),
+            Some(DummyPayload("B".to_string()))
+        );
+
+        state
+            .apply(make_op(
+                1,
+                300,
+                OperationType::Update(DummyPayload("C".to_string())),
+            ))
+            .unwrap();
+        let snapshot = state.storage().load_snapshot(&genesis).unwrap().unwrap();
+        assert_eq!(snapshot.value, Some(DummyPayload("B".to_string())));
+        assert_eq!(snapshot.heads, vec![newest]);
+    }
+
+    #[test]
+    fn test_snapshot_of_other_reducer_is_ignored() {
+        struct FirstReducer;
+        impl Reducer<DummyContentId, DummyPayload> for FirstReducer {
+            const NAME: &'static str = "first";
+
+            fn reduce(ops: &[Operation<DummyContentId, DummyPayload>]) -> Option<DummyPayload> {
+                ops.iter()
+                    .min_by_key(|op| op.timestamp)
+                    .and_then(|op| op.payload().cloned())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let shared = crate::storage::SharedLeveldb::open(dir.path()).unwrap();
+        let genesis = DummyContentId("1".to_string());
+        let lww: CrdtState<DummyContentId, DummyPayload, _, LwwReducer> =
+            CrdtState::new(crate::crdt::storage::LeveldbStorage::new(shared.clone()));
+        lww.apply(make_op(
+            1,
+            100,
+            OperationType::Create(DummyPayload("A".to_string())),
+        ))
+        .unwrap();
+        lww.apply(make_op(
+            1,
+            200,
+            OperationType::Update(DummyPayload("B".to_string())),
+        ))
+        .unwrap();
+
+        let first: CrdtState<DummyContentId, DummyPayload, _, FirstReducer> =
+            CrdtState::new(crate::crdt::storage::LeveldbStorage::new(shared));
+        assert_eq!(
+            first.get_state(&genesis),
+            Some(DummyPayload("A".to_string()))
+        );
+
+        // A non-incremental reducer drops the snapshot it can no longer keep up to date.
+        first
+            .apply(make_op(
+                1,
+                300,
+                OperationType::Update(DummyPayload("C".to_string())),
+            ))
+            .unwrap();
+        assert_eq!(lww.storage().load_snapshot(&genesis).unwrap(), None);
+        assert_eq!(lww.get_state(&genesis), Some(DummyPayload("C".to_string())));
+    }
+
+    #[test]
+    fn test_corrupt_snapshot_falls_back_to_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = crate::storage::SharedLeveldb::open(dir.path()).unwrap();
+        let genesis = DummyContentId("1".to_string());
+        let state: CrdtState<DummyContentId, DummyPayload, _, LwwReducer> =
+            CrdtState::new(crate::crdt::storage::LeveldbStorage::new(shared.clone()));
+        state
+            .apply(make_op(
+                1,
+                100,
+                OperationType::Create(DummyPayload("A".to_string())),
+            ))
+            .unwrap();
+
+        let mut key = vec![0x04];
+        key.extend(bincode::serde::encode_to_vec(&genesis, bincode::config::standard()).unwrap());
+        shared.db().put(&key, &[0xff, 0xff, 0xff]).unwrap();
+        assert!(state.storage().load_snapshot(&genesis).is_err());
+        assert_eq!(
+            state.get_state(&genesis),
+            Some(DummyPayload("A".to_string()))
+        );
+
+        // The next operation replaces the corrupt snapshot.
+        state
+            .apply(make_op(
+                1,
+                200,
+                OperationType::Update(DummyPayload("B".to_string())),
+            ))
+            .unwrap();
+        let snapshot = state.storage().load_snapshot(&genesis).unwrap().unwrap();
+        assert_eq!(snapshot.value, Some(DummyPayload("B".to_string())));
+    }
 }
@@ -1,5 +1,6 @@
 use crate::graph::error::GraphError;
 use bincode::error::{DecodeError, EncodeError};
+use cid::Cid;
 use rusty_leveldb::Status as LeveldbError;
 use thiserror::Error;
 use ulid::DecodeError as UlidDecodeError;
@@ -21,10 +22,62 @@ pub enum CrdtError {
     #[error("graph error: {0}")]
     Graph(#[from] GraphError),
 
+    #[error("merge policy not registered: {0}")]
+    UnknownPolicy(String),
+
+    #[error("merge of {} needs manual resolution: {}", .0.genesis, .0.reason)]
+    MergeConflict(Box<UnresolvedMerge>),
+
+    #[error("genesis {genesis} has multiple heads: {}", format_heads(.heads))]
+    MultipleHeads { genesis: Cid, heads: Vec<Cid> },
+
+    #[error(
+        "branch {} of {} points to {}, expected {}",
+        .0.name,
+        .0.genesis,
+        format_ref(&.0.actual),
+        format_ref(&.0.expected)
+    )]
+    RefMismatch(Box<StaleRef>),
+
+    #[error("timestamp {remote} is more than {max_drift} ns ahead of the local clock")]
+    ClockDrift { remote: u64, max_drift: u64 },
+
     #[error("internal error: {0}")]
     Internal(String),
 }
 
+fn format_heads(heads: &[Cid]) -> String {
+    heads
+        .iter()
+        .map(Cid::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_ref(target: &Option<Cid>) -> String {
+    target.map_or_else(|| "nothing".to_string(), |cid| cid.to_string())
+}
+
+/// Heads of `genesis` the merge policy could not merge; they are left in place until
+/// a resolution is committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnresolvedMerge {
+    pub genesis: Cid,
+    pub candidates: Vec<Cid>,
+    pub reason: String,
+}
+
+/// A branch that did not point at the version a compare-and-swap expected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleRef {
+    pub genesis: Cid,
+    pub name: String,
+    /// The expected tip; `None` when the branch was expected not to exist.
+    pub expected: Option<Cid>,
+    pub actual: Option<Cid>,
+}
+
 #[derive(Error, Debug)]
 pub enum ValidationError {
     #[error("missing CREATE operation for target: {0}")]
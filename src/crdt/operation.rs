@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use ulid::Ulid;
 
-use crate::crdt::timestamp::next_monotonic_timestamp;
+use crate::crdt::timestamp::{global_clock, NodeId};
 
 /// Unique identifier for operations (based on Ulid)
 pub type OperationId = Ulid;
@@ -66,6 +66,19 @@ pub struct Operation<ContentId, T> {
     /// This ensures CID consistency across replicas.
     #[serde(default)]
     pub node_timestamp: Option<Timestamp>,
+    /// Replica that issued the operation, used to break timestamp ties.
+    /// Zero when unknown (e.g. operations created outside a repository).
+    ///
+    /// The serde default only covers self-describing formats such as sync messages;
+    /// `LeveldbStorage` decodes older bincode values itself.
+    #[serde(default)]
+    pub replica: NodeId,
+    /// DAG node produced by the operation, set by the repository when the operation
+    /// is committed. Together with `parents` it lets reducers follow causality.
+    ///
+    /// Defaulted like `replica`, for self-describing formats only.
+    #[serde(default = "Option::default")]
+    pub node: Option<ContentId>,
 }
 
 impl<ContentId, T> Operation<ContentId, T>
@@ -85,7 +98,7 @@ where
     ///
     /// A newly created operation object
     pub fn new(genesis: ContentId, kind: OperationType<T>, author: Author) -> Self {
-        let timestamp = next_monotonic_timestamp();
+        let timestamp = global_clock().now().packed();
         let id = Ulid::new();
         Self {
             id,
@@ -95,6 +108,8 @@ where
             author,
             parents: Vec::new(),
             node_timestamp: None,
+            replica: 0,
+            node: None,
         }
     }
 
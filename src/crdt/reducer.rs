@@ -1,29 +1,205 @@
 use crate::crdt::operation::{Operation, OperationType};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::Hash;
+use ulid::Ulid;
 
 pub trait Reducer<ContentId, T> {
+    /// Stable name recorded in the snapshots the reducer builds.
+    ///
+    /// Snapshots saved under another name are ignored, so renaming a reducer only
+    /// costs a rebuild, while two reducers must never share a name.
+    const NAME: &'static str;
+
     fn reduce(ops: &[Operation<ContentId, T>]) -> Option<T>;
+
+    /// Folds one more operation into `snapshot`, the state of the operations folded
+    /// so far (`None` before the first one).
+    ///
+    /// Folding operations parents first must agree with [`Self::reduce`]. Reducers
+    /// that return `None`, the default, are not incremental and their state is
+    /// recomputed from the whole operation log on every read.
+    fn fold(
+        _snapshot: Option<StateSnapshot<ContentId, T>>,
+        _op: &Operation<ContentId, T>,
+    ) -> Option<StateSnapshot<ContentId, T>> {
+        None
+    }
+
+    /// Builds the snapshot of `ops`, given in any order.
+    ///
+    /// Defaults to folding them in the given order.
+    fn snapshot(ops: &[Operation<ContentId, T>]) -> Option<StateSnapshot<ContentId, T>> {
+        let mut snapshot = None;
+        for op in ops {
+            snapshot = Some(Self::fold(snapshot, op)?);
+        }
+        snapshot
+    }
+}
+
+/// Materialised state of a genesis, maintained by folding operations one at a time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot<ContentId, T> {
+    /// The reduced value.
+    pub value: Option<T>,
+    /// Operations the value is derived from, e.g. the current winner.
+    pub heads: Vec<Operation<ContentId, T>>,
+    /// ID of the last folded operation.
+    pub last_applied: Ulid,
+    /// Name of the reducer that built the snapshot, set by `CrdtState`.
+    #[serde(default)]
+    reducer: String,
+}
+
+impl<ContentId, T> StateSnapshot<ContentId, T> {
+    pub fn new(value: Option<T>, heads: Vec<Operation<ContentId, T>>, last_applied: Ulid) -> Self {
+        Self {
+            value,
+            heads,
+            last_applied,
+            reducer: String::new(),
+        }
+    }
+
+    /// Returns the name of the reducer that built the snapshot.
+    pub fn reducer(&self) -> &str {
+        &self.reducer
+    }
+
+    pub(crate) fn with_reducer(mut self, reducer: &str) -> Self {
+        self.reducer = reducer.to_string();
+        self
+    }
 }
 
 /// Last-Write-Wins reducer: picks the operation with the highest timestamp,
-/// breaking ties by ULID order.
+/// breaking ties by replica ID and then by ULID order.
+///
+/// Its snapshots keep the winning operation as the only head.
 pub struct LwwReducer;
 impl<ContentId, T> Reducer<ContentId, T> for LwwReducer
 where
+    ContentId: Clone,
     T: Clone,
 {
+    const NAME: &'static str = "lww";
+
     fn reduce(ops: &[Operation<ContentId, T>]) -> Option<T> {
         ops.iter()
-            .max_by(|a, b| {
-                a.timestamp
-                    .cmp(&b.timestamp)
-                    .then(a.id.to_bytes().cmp(&b.id.to_bytes()))
-            })
-            .and_then(|op| match &op.kind {
-                OperationType::Create(v) | OperationType::Update(v) | OperationType::Merge(v) => {
-                    Some(v.clone())
-                }
-                OperationType::Delete => None,
-            })
+            .max_by(|a, b| lww_cmp(a, b))
+            .and_then(state_after)
+    }
+
+    fn fold(
+        snapshot: Option<StateSnapshot<ContentId, T>>,
+        op: &Operation<ContentId, T>,
+    ) -> Option<StateSnapshot<ContentId, T>> {
+        let winner = snapshot
+            .and_then(|snapshot| snapshot.heads.into_iter().next())
+            .filter(|winner| lww_cmp(winner, op) == Ordering::Greater)
+            .unwrap_or_else(|| op.clone());
+        Some(StateSnapshot::new(
+            state_after(&winner),
+            vec![winner],
+            op.id,
+        ))
+    }
+}
+
+/// Causal reducer: an operation always wins over the operations it was based on,
+/// whatever their timestamps.
+///
+/// Operations are linked through their `node` and `parents`. Only operations no other
+/// operation builds on (the heads) compete, using the [`LwwReducer`] order. Operations
+/// without a recorded `node` cannot be linked and compete as heads.
+///
+/// Its snapshots keep the heads, so folding relies on parents being folded before
+/// their children, as `Repo` guarantees.
+pub struct CausalReducer;
+impl<ContentId, T> Reducer<ContentId, T> for CausalReducer
+where
+    ContentId: Clone + Eq + Hash,
+    T: Clone,
+{
+    const NAME: &'static str = "causal";
+
+    fn reduce(ops: &[Operation<ContentId, T>]) -> Option<T> {
+        causal_heads(ops)
+            .into_iter()
+            .max_by(|a, b| lww_cmp(a, b))
+            .and_then(state_after)
+    }
+
+    fn fold(
+        snapshot: Option<StateSnapshot<ContentId, T>>,
+        op: &Operation<ContentId, T>,
+    ) -> Option<StateSnapshot<ContentId, T>> {
+        let mut heads = snapshot.map(|snapshot| snapshot.heads).unwrap_or_default();
+        heads.retain(|head| head.id != op.id);
+        heads.push(op.clone());
+        Some(Self::causal_snapshot(&heads, op.id))
+    }
+
+    /// Builds the snapshot of `ops` at once. The latest operation in [`LwwReducer`]
+    /// order is recorded as applied last, whatever order `ops` come in.
+    fn snapshot(ops: &[Operation<ContentId, T>]) -> Option<StateSnapshot<ContentId, T>> {
+        let last = ops.iter().max_by(|a, b| lww_cmp(a, b))?;
+        Some(Self::causal_snapshot(ops, last.id))
+    }
+}
+
+impl CausalReducer {
+    fn causal_snapshot<ContentId, T>(
+        ops: &[Operation<ContentId, T>],
+        last_applied: Ulid,
+    ) -> StateSnapshot<ContentId, T>
+    where
+        ContentId: Clone + Eq + Hash,
+        T: Clone,
+    {
+        let heads: Vec<Operation<ContentId, T>> = causal_heads(ops).into_iter().cloned().collect();
+        StateSnapshot::new(Self::reduce(&heads), heads, last_applied)
+    }
+}
+
+/// Returns the operations no other operation builds on. Of the operations without a
+/// recorded node only the latest is kept, since the others can never win.
+fn causal_heads<ContentId, T>(ops: &[Operation<ContentId, T>]) -> Vec<&Operation<ContentId, T>>
+where
+    ContentId: Eq + Hash,
+{
+    let superseded: HashSet<&ContentId> = ops.iter().flat_map(|op| &op.parents).collect();
+    let mut heads: Vec<&Operation<ContentId, T>> = ops
+        .iter()
+        .filter(|op| {
+            op.node
+                .as_ref()
+                .is_some_and(|node| !superseded.contains(node))
+        })
+        .collect();
+    heads.extend(
+        ops.iter()
+            .filter(|op| op.node.is_none())
+            .max_by(|a, b| lww_cmp(a, b)),
+    );
+    heads
+}
+
+fn lww_cmp<ContentId, T>(a: &Operation<ContentId, T>, b: &Operation<ContentId, T>) -> Ordering {
+    a.timestamp
+        .cmp(&b.timestamp)
+        .then(a.replica.cmp(&b.replica))
+        .then(a.id.to_bytes().cmp(&b.id.to_bytes()))
+}
+
+fn state_after<ContentId, T: Clone>(op: &Operation<ContentId, T>) -> Option<T> {
+    match &op.kind {
+        OperationType::Create(v) | OperationType::Update(v) | OperationType::Merge(v) => {
+            Some(v.clone())
+        }
+        OperationType::Delete => None,
     }
 }
 
@@ -34,7 +210,7 @@ mod tests {
     use serde::{Deserialize, Serialize};
     use ulid::Ulid;
 
-    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     struct DummyContentId(String);
 
     #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -53,6 +229,8 @@ mod tests {
             author: "test".into(),
             parents: Vec::new(),
             node_timestamp: None,
+            replica: 0,
+            node: None,
         }
     }
 
@@ -71,6 +249,8 @@ mod tests {
             author: "test".into(),
             parents: Vec::new(),
             node_timestamp: None,
+            replica: 0,
+            node: None,
         }
     }
 
@@ -132,4 +312,202 @@ mod tests {
 
         assert_eq!(state, Some(DummyPayload("C".into())));
     }
+
+    #[test]
+    fn lww_reducer_same_timestamp_prefers_higher_replica() {
+        let mut op1 = make_op_with_ulid(
+            1,
+            100,
+            OperationType::Update(DummyPayload("A".into())),
+            "01GMTWF9TZQ27MEKTAR4VWZCCT",
+        );
+        op1.replica = 1;
+        let mut op2 = make_op_with_ulid(
+            1,
+            100,
+            OperationType::Update(DummyPayload("B".into())),
+            "01GMTWF61FS176A96AKERBFNNX",
+        );
+        op2.replica = 2;
+
+        assert_eq!(
+            LwwReducer::reduce(&[op1.clone(), op2.clone()]),
+            Some(DummyPayload("B".into()))
+        );
+        assert_eq!(
+            LwwReducer::reduce(&[op2, op1]),
+            Some(DummyPayload("B".into()))
+        );
+    }
+
+    fn linked(
+        node: &str,
+        parents: &[&str],
+        ts: u64,
+        kind: OperationType<DummyPayload>,
+    ) -> Operation<DummyContentId, DummyPayload> {
+        let mut op = make_op(1, ts, kind);
+        op.node = Some(DummyContentId(node.into()));
+        op.parents = parents
+            .iter()
+            .map(|p| DummyContentId(p.to_string()))
+            .collect();
+        op
+    }
+
+    fn update(text: &str) -> OperationType<DummyPayload> {
+        OperationType::Update(DummyPayload(text.into()))
+    }
+
+    #[test]
+    fn causal_reducer_prefers_descendant_over_skewed_ancestor() {
+        // "b" was written on a replica whose clock ran far ahead; "c" builds on it.
+        let ops = vec![
+            linked(
+                "a",
+                &[],
+                100,
+                OperationType::Create(DummyPayload("A".into())),
+            ),
+            linked("b", &["a"], 9_000, update("B")),
+            linked("c", &["b"], 200, update("C")),
+        ];
+
+        assert_eq!(LwwReducer::reduce(&ops), Some(DummyPayload("B".into())));
+        assert_eq!(CausalReducer::reduce(&ops), Some(DummyPayload("C".into())));
+    }
+
+    #[test]
+    fn causal_reducer_breaks_ties_between_concurrent_heads() {
+        let ops = vec![
+            linked(
+                "a",
+                &[],
+                100,
+                OperationType::Create(DummyPayload("A".into())),
+            ),
+            linked("b", &["a"], 9_000, update("B")),
+            linked("old", &["a"], 300, update("old branch")),
+            linked("c", &["b"], 200, update("C")),
+        ];
+        // Heads "c" and "old" are concurrent, so the later timestamp wins.
+        assert_eq!(
+            CausalReducer::reduce(&ops),
+            Some(DummyPayload("old branch".into()))
+        );
+
+        let mut merged = ops.clone();
+        merged.push(linked(
+            "m",
+            &["c", "old"],
+            250,
+            OperationType::Merge(DummyPayload("merged".into())),
+        ));
+        assert_eq!(
+            CausalReducer::reduce(&merged),
+            Some(DummyPayload("merged".into()))
+        );
+    }
+
+    #[test]
+    fn causal_reducer_handles_delete_and_unlinked_ops() {
+        let deleted = vec![
+            linked(
+                "a",
+                &[],
+                100,
+                OperationType::Create(DummyPayload("A".into())),
+            ),
+            linked("d", &["a"], 50, OperationType::Delete),
+        ];
+        assert_eq!(CausalReducer::reduce(&deleted), None);
+
+        // Operations recorded before nodes were tracked fall back to LWW.
+        let legacy = vec![
+            make_op(1, 100, OperationType::Create(DummyPayload("A".into()))),
+            make_op(1, 200, update("B")),
+        ];
+        assert_eq!(
+            CausalReducer::reduce(&legacy),
+            Some(DummyPayload("B".into()))
+        );
+    }
+
+    fn fold_all<R: Reducer<DummyContentId, DummyPayload>>(
+        ops: &[Operation<DummyContentId, DummyPayload>],
+    ) -> StateSnapshot<DummyContentId, DummyPayload> {
+        ops.iter()
+            .fold(None, |snapshot, op| R::fold(snapshot, op))
+            .unwrap()
+    }
+
+    #[test]
+    fn lww_fold_matches_reduce_in_any_order() {
+        let ops = vec![
+            make_op(1, 100, OperationType::Create(DummyPayload("A".into()))),
+            make_op(1, 300, update("B")),
+            make_op(1, 200, update("C")),
+        ];
+        let mut reversed = ops.clone();
+        reversed.reverse();
+
+        for order in [&ops, &reversed] {
+            let snapshot = fold_all::<LwwReducer>(order);
+            assert_eq!(snapshot.value, LwwReducer::reduce(&ops));
+            assert_eq!(snapshot.heads, vec![ops[1].clone()]);
+            assert_eq!(snapshot.last_applied, order[2].id);
+        }
+    }
+
+    #[test]
+    fn causal_fold_tracks_heads() {
+        let ops = vec![
+            linked(
+                "a",
+                &[],
+                100,
+                OperationType::Create(DummyPayload("A".into())),
+            ),
+            linked("b", &["a"], 9_000, update("B")),
+            linked("old", &["a"], 300, update("old branch")),
+            linked("c", &["b"], 200, update("C")),
+            linked(
+                "m",
+                &["c", "old"],
+                250,
+                OperationType::Merge(DummyPayload("merged".into())),
+            ),
+        ];
+
+        for len in 1..=ops.len() {
+            let prefix = &ops[..len];
+            let folded = fold_all::<CausalReducer>(prefix);
+            assert_eq!(folded.value, CausalReducer::reduce(prefix));
+
+            // Rebuilding from the log does not depend on the order of operations.
+            let mut shuffled = prefix.to_vec();
+            shuffled.reverse();
+            let rebuilt = CausalReducer::snapshot(&shuffled).unwrap();
+            assert_eq!(rebuilt.value, folded.value);
+            assert_eq!(rebuilt.heads.len(), folded.heads.len());
+            let latest = prefix.iter().max_by_key(|op| op.timestamp).unwrap();
+            assert_eq!(rebuilt.last_applied, latest.id);
+            assert_eq!(
+                CausalReducer::snapshot(prefix).unwrap().last_applied,
+                latest.id
+            );
+        }
+
+        let snapshot = fold_all::<CausalReducer>(&ops[..4]);
+        let mut heads: Vec<_> = snapshot.heads.iter().map(|op| op.node.clone()).collect();
+        heads.sort_by(|a, b| a.as_ref().map(|n| &n.0).cmp(&b.as_ref().map(|n| &n.0)));
+        assert_eq!(
+            heads,
+            vec![
+                Some(DummyContentId("c".into())),
+                Some(DummyContentId("old".into()))
+            ]
+        );
+        assert_eq!(fold_all::<CausalReducer>(&ops).heads, vec![ops[4].clone()]);
+    }
 }
@@ -1,5 +1,6 @@
 use crate::crdt::error::{CrdtError, Result};
-use crate::crdt::operation::Operation;
+use crate::crdt::operation::{Author, Operation, OperationType, Timestamp};
+use crate::crdt::reducer::StateSnapshot;
 use crate::storage::{BatchError, LeveldbBatchGuard, SharedLeveldb, SharedLeveldbAccess};
 use bincode;
 use rusty_leveldb::LdbIterator;
@@ -8,6 +9,48 @@ use std::path::Path;
 use std::sync::Arc;
 use ulid::Ulid;
 
+/// LevelDB namespace holding serialized operations keyed by their ULID.
+const OPERATION_PREFIX: u8 = 0x01;
+/// LevelDB namespace for the `genesis → operation` secondary index.
+const GENESIS_INDEX_PREFIX: u8 = 0x02;
+/// Marker recording that the genesis index has been built for this store.
+const GENESIS_INDEX_MARKER: &[u8] = b"\x00meta/ops-by-genesis/v1";
+/// LevelDB namespace linking a DAG node to the operation that produced it.
+const NODE_LINK_PREFIX: u8 = 0x03;
+/// LevelDB namespace holding the materialised state snapshot of each genesis.
+const SNAPSHOT_PREFIX: u8 = 0x04;
+/// Leading byte of operations encoded with format version 1 (bincode of
+/// [`Operation`]).
+///
+/// Unversioned values written before start with the length of the ULID string (26),
+/// so they never begin with this byte.
+const OPERATION_FORMAT_V1: u8 = 0xf1;
+
+/// Outcome of [`LeveldbStorage::migrate_genesis_index`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IndexMigration {
+    /// Number of operations added to the index.
+    pub indexed: usize,
+    /// Keys of stored operations that could not be decoded and were left out of the
+    /// index.
+    pub skipped: Vec<Vec<u8>>,
+}
+
+/// Field layout of operations stored before encodings were versioned.
+///
+/// bincode is not self-describing, so fields added to [`Operation`] later cannot be
+/// defaulted by serde; [`LeveldbStorage::decode_operation`] reads them separately.
+#[derive(serde::Deserialize)]
+struct LegacyOperation<ContentId, T> {
+    id: Ulid,
+    genesis: ContentId,
+    kind: OperationType<T>,
+    timestamp: Timestamp,
+    author: Author,
+    parents: Vec<ContentId>,
+    node_timestamp: Option<Timestamp>,
+}
+
 /// Abstraction over the persistent storage used by `CrdtState`.
 pub trait OperationStorage<ContentId, T>: Send + Sync {
     fn save_operation(&self, op: &Operation<ContentId, T>) -> Result<()>;
@@ -17,6 +60,34 @@ pub trait OperationStorage<ContentId, T>: Send + Sync {
     fn begin_batch(&self) -> std::result::Result<LeveldbBatchGuard<'_>, BatchError> {
         Err(BatchError::Unsupported)
     }
+    /// Records that the DAG node `node` was produced by the operation `op_id`.
+    ///
+    /// Backends without a node link index ignore the call.
+    fn link_node(&self, _node: &ContentId, _op_id: &Ulid) -> Result<()> {
+        Ok(())
+    }
+    /// Returns the ID of the operation that produced the DAG node `node`, if recorded.
+    fn get_operation_id_for_node(&self, _node: &ContentId) -> Result<Option<Ulid>> {
+        Ok(None)
+    }
+    /// Returns the state snapshot of `genesis`, including one staged in the active batch.
+    ///
+    /// Backends without snapshot support return `None`, so states are recomputed.
+    fn load_snapshot(&self, _genesis: &ContentId) -> Result<Option<StateSnapshot<ContentId, T>>> {
+        Ok(None)
+    }
+    /// Stores the state snapshot of `genesis`, in the active batch if there is one.
+    fn save_snapshot(
+        &self,
+        _genesis: &ContentId,
+        _snapshot: &StateSnapshot<ContentId, T>,
+    ) -> Result<()> {
+        Ok(())
+    }
+    /// Removes the state snapshot of `genesis`.
+    fn delete_snapshot(&self, _genesis: &ContentId) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// LevelDB-backed implementation of [`OperationStorage`].
@@ -42,21 +113,114 @@ impl<ContentId, T> LeveldbStorage<ContentId, T> {
     /// Builds the LevelDB key prefix used for operations (`0x01` namespace).
     fn make_key(id: &Ulid) -> Vec<u8> {
         let mut key = Vec::with_capacity(1 + 16);
-        key.push(0x01);
+        key.push(OPERATION_PREFIX);
         key.extend_from_slice(id.to_bytes().as_ref());
         key
     }
 
+    /// Builds the index prefix shared by all operations of a genesis (`0x02` namespace).
+    ///
+    /// The genesis is bincode-encoded, which is length-prefixed and therefore never a
+    /// prefix of another genesis' encoding.
+    fn make_index_prefix(genesis: &ContentId) -> Result<Vec<u8>>
+    where
+        ContentId: serde::Serialize,
+    {
+        let encoded = bincode::serde::encode_to_vec(genesis, bincode::config::standard())?;
+        let mut prefix = Vec::with_capacity(1 + encoded.len());
+        prefix.push(GENESIS_INDEX_PREFIX);
+        prefix.extend_from_slice(&encoded);
+        Ok(prefix)
+    }
+
+    /// Builds the index key `0x02 | genesis | op_id` for a single operation.
+    fn make_index_key(genesis: &ContentId, id: &Ulid) -> Result<Vec<u8>>
+    where
+        ContentId: serde::Serialize,
+    {
+        let mut key = Self::make_index_prefix(genesis)?;
+        key.extend_from_slice(id.to_bytes().as_ref());
+        Ok(key)
+    }
+
+    /// Builds the node link key `0x03 | node` (node bincode-encoded).
+    fn make_link_key(node: &ContentId) -> Result<Vec<u8>>
+    where
+        ContentId: serde::Serialize,
+    {
+        let encoded = bincode::serde::encode_to_vec(node, bincode::config::standard())?;
+        let mut key = Vec::with_capacity(1 + encoded.len());
+        key.push(NODE_LINK_PREFIX);
+        key.extend_from_slice(&encoded);
+        Ok(key)
+    }
+
+    /// Builds the snapshot key `0x04 | genesis` (genesis bincode-encoded).
+    fn make_snapshot_key(genesis: &ContentId) -> Result<Vec<u8>>
+    where
+        ContentId: serde::Serialize,
+    {
+        let encoded = bincode::serde::encode_to_vec(genesis, bincode::config::standard())?;
+        let mut key = Vec::with_capacity(1 + encoded.len());
+        key.push(SNAPSHOT_PREFIX);
+        key.extend_from_slice(&encoded);
+        Ok(key)
+    }
+
     /// Serialises an operation into the binary format persisted in LevelDB.
     fn encode_operation(op: &Operation<ContentId, T>) -> Result<Vec<u8>>
     where
         ContentId: serde::Serialize,
         T: serde::Serialize,
     {
-        let value = bincode::serde::encode_to_vec(op, bincode::config::standard())?;
+        let mut value = vec![OPERATION_FORMAT_V1];
+        value.extend(bincode::serde::encode_to_vec(
+            op,
+            bincode::config::standard(),
+        )?);
         Ok(value)
     }
 
+    /// Decodes an operation written by any version of the storage.
+    ///
+    /// Unversioned values hold the fields of [`LegacyOperation`] followed by the
+    /// fields added until versioning was introduced, each of which may be missing.
+    fn decode_operation(raw: &[u8]) -> Result<Operation<ContentId, T>>
+    where
+        ContentId: for<'de> serde::Deserialize<'de>,
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        let config = bincode::config::standard();
+        if let Some((&OPERATION_FORMAT_V1, encoded)) = raw.split_first() {
+            let (op, _) = bincode::serde::decode_from_slice(encoded, config)?;
+            return Ok(op);
+        }
+
+        let (legacy, read) =
+            bincode::serde::decode_from_slice::<LegacyOperation<ContentId, T>, _>(raw, config)?;
+        let mut op = Operation {
+            id: legacy.id,
+            genesis: legacy.genesis,
+            kind: legacy.kind,
+            timestamp: legacy.timestamp,
+            author: legacy.author,
+            parents: legacy.parents,
+            node_timestamp: legacy.node_timestamp,
+            replica: 0,
+            node: None,
+        };
+        let mut rest = &raw[read..];
+        if !rest.is_empty() {
+            let read;
+            (op.replica, read) = bincode::serde::decode_from_slice(rest, config)?;
+            rest = &rest[read..];
+        }
+        if !rest.is_empty() {
+            (op.node, _) = bincode::serde::decode_from_slice(rest, config)?;
+        }
+        Ok(op)
+    }
+
     /// Writes value bytes either to the active batch or directly to the DB.
     fn put_bytes(&self, key: &[u8], value: &[u8]) -> Result<()> {
         if self
@@ -82,6 +246,78 @@ impl<ContentId, T> LeveldbStorage<ContentId, T> {
     }
 }
 
+impl<ContentId, T> LeveldbStorage<ContentId, T>
+where
+    ContentId: serde::Serialize + for<'de> serde::Deserialize<'de>,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+    /// Builds the genesis index for operations written before the index existed.
+    ///
+    /// The migration scans the operation namespace once, writes all index entries
+    /// together with a completion marker in a single LevelDB write, and is a no-op
+    /// once the marker is present. Operations that cannot be decoded do not stop the
+    /// migration; they are left out of the index and listed in the result.
+    ///
+    /// The write goes straight to the database, even while a batch is active. It only
+    /// indexes operations that are already committed, so it stays valid whether or not
+    /// that batch is committed; operations staged in the batch index themselves.
+    pub fn migrate_genesis_index(&self) -> Result<IndexMigration> {
+        let mut report = IndexMigration::default();
+        if self.shared.db().get(GENESIS_INDEX_MARKER).is_some() {
+            return Ok(report);
+        }
+
+        let mut batch = rusty_leveldb::WriteBatch::default();
+        {
+            let mut iter = self.shared.db().new_iter().map_err(CrdtError::Storage)?;
+            iter.seek(&[OPERATION_PREFIX]);
+
+            let mut key = Vec::new();
+            let mut value = Vec::new();
+            while iter.valid() {
+                iter.current(&mut key, &mut value);
+                if key.first() != Some(&OPERATION_PREFIX) {
+                    break;
+                }
+                match Self::decode_operation(&value) {
+                    Ok(op) => {
+                        batch.put(&Self::make_index_key(&op.genesis, &op.id)?, &[]);
+                        report.indexed += 1;
+                    }
+                    Err(_) => report.skipped.push(key.clone()),
+                }
+                iter.advance();
+            }
+        }
+        batch.put(GENESIS_INDEX_MARKER, b"1");
+        self.shared.db().write(batch, true)?;
+        Ok(report)
+    }
+
+    /// Collects the operation IDs recorded under the given genesis in the index.
+    fn indexed_operation_ids(&self, genesis: &ContentId) -> Result<Vec<Ulid>> {
+        let prefix = Self::make_index_prefix(genesis)?;
+        let mut ids = Vec::new();
+        let mut iter = self.shared.db().new_iter().map_err(CrdtError::Storage)?;
+        iter.seek(&prefix);
+
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+        while iter.valid() {
+            iter.current(&mut key, &mut value);
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let id_bytes: [u8; 16] = key[prefix.len()..].try_into().map_err(|_| {
+                CrdtError::Internal(format!("malformed genesis index key: {key:?}"))
+            })?;
+            ids.push(Ulid::from_bytes(id_bytes));
+            iter.advance();
+        }
+        Ok(ids)
+    }
+}
+
 impl<ContentId, T> SharedLeveldbAccess for LeveldbStorage<ContentId, T> {
     fn shared_leveldb(&self) -> Option<Arc<SharedLeveldb>> {
         Some(self.shared.clone())
@@ -105,27 +341,24 @@ where
     fn save_operation(&self, op: &Operation<ContentId, T>) -> Result<()> {
         let key = Self::make_key(&op.id);
         let value = Self::encode_operation(op)?;
-        self.put_bytes(&key, &value)
+        self.put_bytes(&key, &value)?;
+        let index_key = Self::make_index_key(&op.genesis, &op.id)?;
+        self.put_bytes(&index_key, &[])
     }
 
+    /// Loads all operations of a genesis through the `0x02` genesis index.
+    ///
+    /// Stores created before the index existed are migrated on first use.
     fn load_operations(&self, genesis: &ContentId) -> Result<Vec<Operation<ContentId, T>>> {
-        let mut result = Vec::new();
-        let mut iter = self.shared.db().new_iter().map_err(CrdtError::Storage)?;
-        iter.seek_to_first();
+        self.migrate_genesis_index()?;
 
-        let mut key = Vec::new();
-        let mut value = Vec::new();
-        while iter.valid() {
-            iter.current(&mut key, &mut value);
-            if let Ok((op, _)) = bincode::serde::decode_from_slice::<Operation<ContentId, T>, _>(
-                &value,
-                bincode::config::standard(),
-            ) {
+        let mut result = Vec::new();
+        for id in self.indexed_operation_ids(genesis)? {
+            if let Some(op) = self.get_operation(&id)? {
                 if op.genesis == *genesis {
                     result.push(op);
                 }
             }
-            iter.advance();
         }
 
         Ok(result)
@@ -134,21 +367,66 @@ where
     fn get_operation(&self, op_id: &Ulid) -> Result<Option<Operation<ContentId, T>>> {
         let key = Self::make_key(op_id);
         match self.shared.db().get(&key) {
-            Some(raw) => {
-                let (op, _) = bincode::serde::decode_from_slice::<Operation<ContentId, T>, _>(
-                    &raw,
-                    bincode::config::standard(),
-                )?;
-                Ok(Some(op))
-            }
+            Some(raw) => Ok(Some(Self::decode_operation(&raw)?)),
             None => Ok(None),
         }
     }
 
     fn delete_operation(&self, op_id: &Ulid) -> Result<()> {
+        if let Some(op) = self.get_operation(op_id)? {
+            let index_key = Self::make_index_key(&op.genesis, op_id)?;
+            self.delete_key(&index_key)?;
+        }
         let key = Self::make_key(op_id);
         self.delete_key(&key)
     }
+
+    fn link_node(&self, node: &ContentId, op_id: &Ulid) -> Result<()> {
+        let key = Self::make_link_key(node)?;
+        self.put_bytes(&key, op_id.to_bytes().as_ref())
+    }
+
+    fn load_snapshot(&self, genesis: &ContentId) -> Result<Option<StateSnapshot<ContentId, T>>> {
+        let key = Self::make_snapshot_key(genesis)?;
+        match self.shared.get_staged(&key) {
+            Some(raw) => {
+                let (snapshot, _) = bincode::serde::decode_from_slice::<
+                    StateSnapshot<ContentId, T>,
+                    _,
+                >(&raw, bincode::config::standard())?;
+                Ok(Some(snapshot))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn save_snapshot(
+        &self,
+        genesis: &ContentId,
+        snapshot: &StateSnapshot<ContentId, T>,
+    ) -> Result<()> {
+        let key = Self::make_snapshot_key(genesis)?;
+        let value = bincode::serde::encode_to_vec(snapshot, bincode::config::standard())?;
+        self.put_bytes(&key, &value)
+    }
+
+    fn delete_snapshot(&self, genesis: &ContentId) -> Result<()> {
+        let key = Self::make_snapshot_key(genesis)?;
+        self.delete_key(&key)
+    }
+
+    fn get_operation_id_for_node(&self, node: &ContentId) -> Result<Option<Ulid>> {
+        let key = Self::make_link_key(node)?;
+        match self.shared.db().get(&key) {
+            Some(raw) => {
+                let id_bytes: [u8; 16] = raw.as_slice().try_into().map_err(|_| {
+                    CrdtError::Internal(format!("malformed node link value: {raw:?}"))
+                })?;
+                Ok(Some(Ulid::from_bytes(id_bytes)))
+            }
+            None => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -233,4 +511,214 @@ mod tests {
         assert!(all.contains(&op_a));
         assert!(all.contains(&op_b));
     }
+
+    #[test]
+    fn load_operations_only_returns_matching_genesis() {
+        let (storage, _dir) = setup_storage();
+        let op_a = make_op(1, "a");
+        let op_b = make_op(2, "b");
+        let op_c = make_op(1, "c");
+        storage.save_operation(&op_a).unwrap();
+        storage.save_operation(&op_b).unwrap();
+        storage.save_operation(&op_c).unwrap();
+
+        let ops = storage.load_operations(&DummyContentId(1)).unwrap();
+        assert_eq!(ops.len(), 2);
+        assert!(ops.contains(&op_a));
+        assert!(ops.contains(&op_c));
+
+        let ops = storage.load_operations(&DummyContentId(2)).unwrap();
+        assert_eq!(ops, vec![op_b]);
+    }
+
+    #[test]
+    fn delete_operation_removes_index_entry() {
+        let (storage, _dir) = setup_storage();
+        let op = make_op(3, "gone");
+        storage.save_operation(&op).unwrap();
+
+        storage.delete_operation(&op.id).unwrap();
+
+        assert!(storage
+            .load_operations(&DummyContentId(3))
+            .unwrap()
+            .is_empty());
+        let index_key = LeveldbStorage::<DummyContentId, DummyPayload>::make_index_key(
+            &DummyContentId(3),
+            &op.id,
+        )
+        .unwrap();
+        assert!(storage.shared.db().get(&index_key).is_none());
+    }
+
+    #[test]
+    fn link_node_roundtrip() {
+        let (storage, _dir) = setup_storage();
+        let op = make_op(4, "linked");
+        storage.save_operation(&op).unwrap();
+
+        assert_eq!(
+            storage
+                .get_operation_id_for_node(&DummyContentId(40))
+                .unwrap(),
+            None
+        );
+        storage.link_node(&DummyContentId(40), &op.id).unwrap();
+        assert_eq!(
+            storage
+                .get_operation_id_for_node(&DummyContentId(40))
+                .unwrap(),
+            Some(op.id)
+        );
+    }
+
+    #[test]
+    fn migration_indexes_legacy_operations() {
+        let (storage, _dir) = setup_storage();
+        let legacy = make_op(5, "legacy");
+        let value =
+            LeveldbStorage::<DummyContentId, DummyPayload>::encode_operation(&legacy).unwrap();
+        storage
+            .shared
+            .db()
+            .put(
+                &LeveldbStorage::<DummyContentId, DummyPayload>::make_key(&legacy.id),
+                &value,
+            )
+            .unwrap();
+
+        assert_eq!(storage.migrate_genesis_index().unwrap().indexed, 1);
+        assert_eq!(
+            storage.migrate_genesis_index().unwrap(),
+            IndexMigration::default(),
+            "migration should only run once"
+        );
+
+        let ops = storage.load_operations(&DummyContentId(5)).unwrap();
+        assert_eq!(ops, vec![legacy]);
+    }
+
+    #[test]
+    fn migration_skips_undecodable_operations() {
+        let (storage, _dir) = setup_storage();
+        let legacy = make_op(6, "legacy");
+        let value =
+            LeveldbStorage::<DummyContentId, DummyPayload>::encode_operation(&legacy).unwrap();
+        let corrupt_key = LeveldbStorage::<DummyContentId, DummyPayload>::make_key(&Ulid::new());
+        let mut db = storage.shared.db();
+        db.put(
+            &LeveldbStorage::<DummyContentId, DummyPayload>::make_key(&legacy.id),
+            &value,
+        )
+        .unwrap();
+        db.put(&corrupt_key, &[0xff, 0x00, 0x13]).unwrap();
+        drop(db);
+
+        let report = storage.migrate_genesis_index().unwrap();
+        assert_eq!(report.indexed, 1);
+        assert_eq!(report.skipped, vec![corrupt_key]);
+
+        let ops = storage.load_operations(&DummyContentId(6)).unwrap();
+        assert_eq!(ops, vec![legacy]);
+    }
+
+    #[test]
+    fn staged_snapshot_is_visible_until_batch_is_dropped() {
+        let (storage, _dir) = setup_storage();
+        let genesis = DummyContentId(7);
+        let op = make_op(7, "staged");
+        let snapshot =
+            StateSnapshot::new(Some(DummyPayload("staged".into())), vec![op.clone()], op.id);
+
+        let guard = storage.begin_batch().unwrap();
+        storage.save_snapshot(&genesis, &snapshot).unwrap();
+        assert_eq!(
+            storage.load_snapshot(&genesis).unwrap(),
+            Some(snapshot.clone())
+        );
+        drop(guard);
+        assert_eq!(storage.load_snapshot(&genesis).unwrap(), None);
+
+        let guard = storage.begin_batch().unwrap();
+        storage.save_snapshot(&genesis, &snapshot).unwrap();
+        guard.commit().unwrap();
+        assert_eq!(storage.load_snapshot(&genesis).unwrap(), Some(snapshot));
+
+        storage.delete_snapshot(&genesis).unwrap();
+        assert_eq!(storage.load_snapshot(&genesis).unwrap(), None);
+    }
+
+    /// Operation layout of stores written before replica IDs were recorded.
+    #[derive(Serialize)]
+    struct BaselineOperation {
+        id: Ulid,
+        genesis: DummyContentId,
+        kind: OperationType<DummyPayload>,
+        timestamp: u64,
+        author: String,
+        parents: Vec<DummyContentId>,
+        node_timestamp: Option<u64>,
+    }
+
+    fn encode<V: Serialize>(value: &V) -> Vec<u8> {
+        bincode::serde::encode_to_vec(value, bincode::config::standard()).unwrap()
+    }
+
+    fn baseline_bytes(op: &Operation<DummyContentId, DummyPayload>) -> Vec<u8> {
+        let baseline = BaselineOperation {
+            id: op.id,
+            genesis: op.genesis.clone(),
+            kind: op.kind.clone(),
+            timestamp: op.timestamp,
+            author: op.author.clone(),
+            parents: op.parents.clone(),
+            node_timestamp: op.node_timestamp,
+        };
+        encode(&baseline)
+    }
+
+    #[test]
+    fn unversioned_operations_still_decode() {
+        let (storage, _dir) = setup_storage();
+        let mut baseline = make_op(9, "baseline");
+        baseline.replica = 0;
+        let raw = baseline_bytes(&baseline);
+        assert_ne!(raw[0], OPERATION_FORMAT_V1);
+
+        let mut with_replica = make_op(9, "with-replica");
+        with_replica.replica = 42;
+        let mut replica_raw = baseline_bytes(&with_replica);
+        replica_raw.extend(encode(&42u64));
+
+        let mut with_node = make_op(9, "with-node");
+        with_node.replica = 7;
+        with_node.node = Some(DummyContentId(10));
+        let mut node_raw = baseline_bytes(&with_node);
+        node_raw.extend(encode(&7u64));
+        node_raw.extend(encode(&with_node.node));
+
+        for (op, raw) in [
+            (&baseline, &raw),
+            (&with_replica, &replica_raw),
+            (&with_node, &node_raw),
+        ] {
+            storage
+                .shared
+                .db()
+                .put(
+                    &LeveldbStorage::<DummyContentId, DummyPayload>::make_key(&op.id),
+                    raw,
+                )
+                .unwrap();
+        }
+
+        assert_eq!(storage.migrate_genesis_index().unwrap().indexed, 3);
+        assert_eq!(
+            storage.get_operation(&baseline.id).unwrap(),
+            Some(baseline.clone())
+        );
+        let mut ops = storage.load_operations(&DummyContentId(9)).unwrap();
+        ops.sort_by_key(|op| op.timestamp);
+        assert_eq!(ops, vec![baseline, with_replica, with_node]);
+    }
 }
@@ -1,8 +1,26 @@
+use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::crdt::error::{CrdtError, Result};
 use crate::crdt::operation::Timestamp;
 
+/// Number of low bits of a packed timestamp reserved for the logical counter.
+///
+/// The remaining high bits hold wall-clock nanoseconds truncated to a 2^16 ns tick,
+/// so packed HLC timestamps stay comparable with plain nanosecond timestamps.
+pub const LOGICAL_BITS: u32 = 16;
+const LOGICAL_MASK: u64 = (1 << LOGICAL_BITS) - 1;
+
+/// How far, in nanoseconds, a remote timestamp may run ahead of the local wall clock.
+///
+/// [`HybridLogicalClock::observe`] rejects timestamps beyond it, so one replica with a
+/// wildly wrong clock cannot drag every other clock into the future.
+pub const MAX_CLOCK_DRIFT: u64 = 5 * 60 * 1_000_000_000;
+
+/// Identifier of the replica that issued an operation, see `Operation::replica`.
+pub type NodeId = u64;
+
 /// Returns the current time in nanoseconds since the Unix epoch.
 fn current_timestamp_nanos() -> Timestamp {
     SystemTime::now()
@@ -11,41 +29,124 @@ fn current_timestamp_nanos() -> Timestamp {
         .as_nanos() as u64
 }
 
-static LAST_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
+/// Returns the current wall-clock time with the logical bits cleared.
+fn current_physical() -> Timestamp {
+    current_timestamp_nanos() & !LOGICAL_MASK
+}
 
-/// Generates a monotonically increasing timestamp.
-pub fn next_monotonic_timestamp() -> Timestamp {
-    let mut ts = current_timestamp_nanos();
-
-    loop {
-        let last = LAST_TIMESTAMP.load(Ordering::Relaxed);
-        if ts > last {
-            match LAST_TIMESTAMP.compare_exchange(last, ts, Ordering::AcqRel, Ordering::Relaxed) {
-                Ok(_) => return ts,
-                Err(_) => ts = current_timestamp_nanos(),
-            }
-        } else {
-            let candidate = last.wrapping_add(1);
-            if candidate == 0 {
-                ts = current_timestamp_nanos();
-                continue;
-            }
-            match LAST_TIMESTAMP.compare_exchange(
-                last,
-                candidate,
-                Ordering::AcqRel,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => return candidate,
-                Err(_) => ts = current_timestamp_nanos(),
+/// A reading of a [`HybridLogicalClock`].
+///
+/// Ordering compares the physical component, then the logical counter. Equal
+/// timestamps from different replicas are told apart by `Operation::replica`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct HybridTimestamp {
+    /// Wall-clock nanoseconds, truncated to the physical tick.
+    pub physical: u64,
+    /// Counter distinguishing events within the same physical tick.
+    pub logical: u16,
+}
+
+impl HybridTimestamp {
+    /// Splits a packed `u64` timestamp into its physical and logical components.
+    pub fn from_packed(packed: Timestamp) -> Self {
+        Self {
+            physical: packed & !LOGICAL_MASK,
+            logical: (packed & LOGICAL_MASK) as u16,
+        }
+    }
+
+    /// Packs the physical and logical components into the `u64` stored in
+    /// operations and DAG nodes.
+    pub fn packed(&self) -> Timestamp {
+        (self.physical & !LOGICAL_MASK) | self.logical as u64
+    }
+}
+
+/// Hybrid logical clock (HLC) issuing packed timestamps.
+///
+/// Local events take the maximum of the wall clock and the last issued timestamp
+/// plus one. Timestamps received from other replicas are fed to [`Self::observe`],
+/// which moves the clock past them, so a replica with a skewed clock cannot keep
+/// winning last-writer-wins comparisons against causally later writes.
+///
+/// The clock is shared by every repository in the process, so it carries no replica
+/// ID of its own; each `SharedLeveldb` stamps its replica ID on the operations it
+/// commits.
+#[derive(Debug, Default)]
+pub struct HybridLogicalClock {
+    last: AtomicU64,
+}
+
+impl HybridLogicalClock {
+    pub const fn new() -> Self {
+        Self {
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the last packed timestamp issued or observed by this clock.
+    pub fn last(&self) -> Timestamp {
+        self.last.load(Ordering::Acquire)
+    }
+
+    /// Issues a timestamp for a local event.
+    pub fn now(&self) -> HybridTimestamp {
+        self.advance(0)
+    }
+
+    /// Advances the clock past a timestamp received from another replica and
+    /// returns the resulting local timestamp.
+    ///
+    /// Fails with [`CrdtError::ClockDrift`], leaving the clock unchanged, when `remote`
+    /// is more than [`MAX_CLOCK_DRIFT`] ahead of the local wall clock.
+    pub fn observe(&self, remote: Timestamp) -> Result<HybridTimestamp> {
+        if remote > current_physical().saturating_add(MAX_CLOCK_DRIFT) {
+            return Err(CrdtError::ClockDrift {
+                remote,
+                max_drift: MAX_CLOCK_DRIFT,
+            });
+        }
+        Ok(self.advance(remote))
+    }
+
+    /// Advances the clock past a timestamp this process issued earlier, such as the
+    /// watermark persisted by a store. Unlike [`Self::observe`] there is no drift bound.
+    pub(crate) fn restore(&self, issued: Timestamp) -> HybridTimestamp {
+        self.advance(issued)
+    }
+
+    fn advance(&self, remote: Timestamp) -> HybridTimestamp {
+        loop {
+            let last = self.last.load(Ordering::Acquire);
+            let next = current_physical()
+                .max(last.saturating_add(1))
+                .max(remote.saturating_add(1));
+            if self
+                .last
+                .compare_exchange(last, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return HybridTimestamp::from_packed(next);
             }
         }
     }
 }
 
+static GLOBAL_CLOCK: HybridLogicalClock = HybridLogicalClock::new();
+
+/// Returns the process-wide clock used by `Operation::new`, auto-merge and imports.
+pub fn global_clock() -> &'static HybridLogicalClock {
+    &GLOBAL_CLOCK
+}
+
+/// Generates a monotonically increasing timestamp from the global HLC.
+pub fn next_monotonic_timestamp() -> Timestamp {
+    global_clock().now().packed()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::next_monotonic_timestamp;
+    use super::*;
 
     #[test]
     fn timestamps_are_monotonic() {
@@ -66,4 +167,57 @@ mod tests {
             last = current;
         }
     }
+
+    #[test]
+    fn packing_roundtrip() {
+        let ts = HybridTimestamp {
+            physical: 0x1234_5678_0000,
+            logical: 42,
+        };
+        let packed = ts.packed();
+        assert_eq!(HybridTimestamp::from_packed(packed), ts);
+    }
+
+    #[test]
+    fn observe_moves_clock_past_remote() {
+        let clock = HybridLogicalClock::new();
+        let remote = current_timestamp_nanos() + 60_000_000_000;
+
+        let received = clock.observe(remote).unwrap();
+        assert!(received.packed() > remote);
+
+        let local = clock.now();
+        assert!(local > received);
+        assert!(local.packed() > remote);
+    }
+
+    #[test]
+    fn observe_ignores_remote_in_the_past() {
+        let clock = HybridLogicalClock::new();
+        let before = clock.now();
+
+        let after = clock.observe(1).unwrap();
+        assert!(after > before);
+    }
+
+    #[test]
+    fn observe_rejects_remote_beyond_max_drift() {
+        let clock = HybridLogicalClock::new();
+        let before = clock.now();
+        let remote = current_timestamp_nanos() + MAX_CLOCK_DRIFT + 60_000_000_000;
+
+        let err = clock.observe(remote).unwrap_err();
+        assert!(matches!(err, CrdtError::ClockDrift { remote: r, .. } if r == remote));
+        assert_eq!(clock.last(), before.packed());
+        assert!(clock.now().packed() < remote);
+    }
+
+    #[test]
+    fn ordering_follows_packed_value() {
+        let a = HybridTimestamp::from_packed(1 << LOGICAL_BITS);
+        let b = HybridTimestamp::from_packed((1 << LOGICAL_BITS) + 1);
+        let c = HybridTimestamp::from_packed(2 << LOGICAL_BITS);
+        assert!(a < b && b < c);
+        assert!(a.packed() < b.packed() && b.packed() < c.packed());
+    }
 }
@@ -0,0 +1,213 @@
+use super::dag_cbor;
+use super::error::{DaslError, Result};
+use cid::Cid;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Only CARv1 is supported.
+/// See https://ipld.io/specs/transport/car/carv1/
+pub const CAR_VERSION: u64 = 1;
+
+/// Upper bound for a single header or block section, guarding against corrupt lengths.
+const MAX_SECTION_LEN: u64 = 64 * 1024 * 1024;
+
+/// Writes a CARv1 stream: a DAG-CBOR header listing the roots followed by
+/// `varint(len) | cid | data` block sections.
+pub struct CarWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CarWriter<W> {
+    /// Creates a writer and immediately writes the header for `roots`.
+    pub fn new(mut writer: W, roots: &[Cid]) -> Result<Self> {
+        let header = encode_header(roots)?;
+        write_varint(&mut writer, header.len() as u64)?;
+        writer.write_all(&header)?;
+        Ok(Self { writer })
+    }
+
+    /// Appends a block. The caller is responsible for `cid` matching `data`.
+    pub fn write_block(&mut self, cid: &Cid, data: &[u8]) -> Result<()> {
+        let cid_bytes = cid.to_bytes();
+        write_varint(&mut self.writer, (cid_bytes.len() + data.len()) as u64)?;
+        self.writer.write_all(&cid_bytes)?;
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads a CARv1 stream written by [`CarWriter`] or other IPLD tooling.
+pub struct CarReader<R: Read> {
+    reader: R,
+    roots: Vec<Cid>,
+}
+
+impl<R: Read> CarReader<R> {
+    /// Creates a reader and parses the header.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = read_varint(&mut reader)?
+            .ok_or_else(|| DaslError::Car("missing CAR header".to_string()))?;
+        let header = read_section(&mut reader, len)?;
+        let roots = decode_header(&header)?;
+        Ok(Self { reader, roots })
+    }
+
+    /// Returns the roots listed in the header.
+    pub fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+
+    /// Reads the next block, or `None` at the end of the stream.
+    pub fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>> {
+        let Some(len) = read_varint(&mut self.reader)? else {
+            return Ok(None);
+        };
+        let section = read_section(&mut self.reader, len)?;
+        let mut rest = section.as_slice();
+        let cid = Cid::read_bytes(&mut rest)?;
+        Ok(Some((cid, rest.to_vec())))
+    }
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> Result<()> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&buf[..len])?;
+    Ok(())
+}
+
+/// Reads an unsigned LEB128 varint. Returns `None` on a clean end of stream.
+fn read_varint<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value = 0u64;
+    for index in 0..10 {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            if index == 0 {
+                return Ok(None);
+            }
+            return Err(DaslError::Car("truncated varint".to_string()));
+        }
+        value |= u64::from(byte[0] & 0x7f) << (7 * index);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(DaslError::Car("varint overflow".to_string()))
+}
+
+fn read_section<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_SECTION_LEN {
+        return Err(DaslError::Car(format!("section too large: {len} bytes")));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Encodes `{"roots": [link...], "version": 1}` as DAG-CBOR.
+fn encode_header(roots: &[Cid]) -> Result<Vec<u8>> {
+    let mut header = BTreeMap::new();
+    header.insert(
+        Value::Text("roots".to_string()),
+        Value::Array(roots.iter().map(dag_cbor::link).collect()),
+    );
+    header.insert(
+        Value::Text("version".to_string()),
+        Value::Integer(CAR_VERSION.into()),
+    );
+    dag_cbor::to_vec(&Value::Map(header))
+}
+
+fn decode_header(buf: &[u8]) -> Result<Vec<Cid>> {
+    let Value::Map(mut header) = dag_cbor::from_slice(buf)? else {
+        return Err(DaslError::Car("CAR header is not a map".to_string()));
+    };
+    match header.remove(&Value::Text("version".to_string())) {
+        Some(Value::Integer(version)) if version == CAR_VERSION.into() => {}
+        Some(other) => return Err(DaslError::Car(format!("unsupported CAR version {other:?}"))),
+        None => return Err(DaslError::Car("CAR header lacks version".to_string())),
+    }
+    match header.remove(&Value::Text("roots".to_string())) {
+        Some(Value::Array(roots)) => roots.iter().map(dag_cbor::parse_link).collect(),
+        _ => Err(DaslError::Car("CAR header lacks roots".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dasl::cid::ContentId;
+
+    #[test]
+    fn test_car_roundtrip() {
+        let first = ContentId::new(b"first").unwrap().0;
+        let second = ContentId::new(b"second").unwrap().0;
+
+        let mut writer = CarWriter::new(Vec::new(), &[second]).unwrap();
+        writer.write_block(&first, b"first").unwrap();
+        writer.write_block(&second, b"second").unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.roots(), &[second]);
+        assert_eq!(
+            reader.next_block().unwrap(),
+            Some((first, b"first".to_vec()))
+        );
+        assert_eq!(
+            reader.next_block().unwrap(),
+            Some((second, b"second".to_vec()))
+        );
+        assert_eq!(reader.next_block().unwrap(), None);
+    }
+
+    #[test]
+    fn test_header_matches_reference_encoding() {
+        // Header of a CARv1 file with no roots: {"roots": [], "version": 1}
+        let expected = [
+            0xa2, 0x65, b'r', b'o', b'o', b't', b's', 0x80, 0x67, b'v', b'e', b'r', b's', b'i',
+            b'o', b'n', 0x01,
+        ];
+        assert_eq!(encode_header(&[]).unwrap(), expected);
+        assert!(decode_header(&expected).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value).unwrap();
+            assert_eq!(read_varint(&mut buf.as_slice()).unwrap(), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_truncated_block_is_rejected() {
+        let cid = ContentId::new(b"data").unwrap().0;
+        let mut writer = CarWriter::new(Vec::new(), &[cid]).unwrap();
+        writer.write_block(&cid, b"data").unwrap();
+        let mut bytes = writer.finish().unwrap();
+        bytes.truncate(bytes.len() - 2);
+
+        let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next_block().is_err());
+    }
+}
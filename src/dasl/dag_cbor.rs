@@ -0,0 +1,315 @@
+//! Minimal DAG-CBOR codec over [`serde_cbor::Value`].
+//!
+//! Encoding follows the DAG-CBOR / DASL rules: definite lengths only, shortest
+//! integer and length heads, 64-bit floats, text map keys sorted by length and
+//! then bytewise, and links as CBOR tag 42 over `0x00 | cid bytes`.
+//! See https://ipld.io/specs/codecs/dag-cbor/spec/
+
+use super::error::{DaslError, Result};
+use cid::Cid;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+
+/// Multicodec code of DAG-CBOR.
+pub const DAG_CBOR_CODE: u64 = 0x71;
+
+/// CBOR tag for IPLD links (CIDs).
+const CID_TAG: u64 = 42;
+/// Nesting limit for decoding, guarding against stack exhaustion.
+const MAX_DEPTH: usize = 128;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+const FALSE: u8 = 0xf4;
+const TRUE: u8 = 0xf5;
+const NULL: u8 = 0xf6;
+const FLOAT64: u8 = 0xfb;
+
+/// Returns the DAG-CBOR representation of a link to `cid`.
+pub fn link(cid: &Cid) -> Value {
+    let mut bytes = vec![0];
+    bytes.extend_from_slice(&cid.to_bytes());
+    Value::Tag(CID_TAG, Box::new(Value::Bytes(bytes)))
+}
+
+/// Parses a link produced by [`link`] or [`from_slice`].
+pub fn parse_link(value: &Value) -> Result<Cid> {
+    match value {
+        Value::Tag(CID_TAG, inner) => match inner.as_ref() {
+            Value::Bytes(bytes) => match bytes.split_first() {
+                Some((0, cid_bytes)) => Ok(Cid::try_from(cid_bytes)?),
+                _ => Err(invalid("link lacks the 0x00 multibase prefix")),
+            },
+            _ => Err(invalid("link is not a byte string")),
+        },
+        _ => Err(invalid("value is not a link")),
+    }
+}
+
+/// Encodes a value as canonical DAG-CBOR.
+pub fn to_vec(value: &Value) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode(value, &mut out)?;
+    Ok(out)
+}
+
+/// Decodes a single DAG-CBOR value, rejecting trailing bytes.
+pub fn from_slice(buf: &[u8]) -> Result<Value> {
+    let mut decoder = Decoder { buf };
+    let value = decoder.value(0)?;
+    if !decoder.buf.is_empty() {
+        return Err(invalid("trailing bytes after value"));
+    }
+    Ok(value)
+}
+
+fn invalid(message: &str) -> DaslError {
+    DaslError::Deserialization {
+        message: format!("invalid DAG-CBOR: {message}"),
+    }
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match value {
+        Value::Null => out.push(NULL),
+        Value::Bool(false) => out.push(FALSE),
+        Value::Bool(true) => out.push(TRUE),
+        Value::Integer(int) => {
+            if *int >= 0 {
+                let value = u64::try_from(*int).map_err(|_| invalid("integer out of range"))?;
+                write_head(out, MAJOR_UNSIGNED, value);
+            } else {
+                let value =
+                    u64::try_from(-1 - *int).map_err(|_| invalid("integer out of range"))?;
+                write_head(out, MAJOR_NEGATIVE, value);
+            }
+        }
+        Value::Float(float) => {
+            if !float.is_finite() {
+                return Err(invalid("NaN and infinities are not allowed"));
+            }
+            out.push(FLOAT64);
+            out.extend_from_slice(&float.to_be_bytes());
+        }
+        Value::Bytes(bytes) => {
+            write_head(out, MAJOR_BYTES, bytes.len() as u64);
+            out.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            write_head(out, MAJOR_TEXT, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(out, MAJOR_ARRAY, items.len() as u64);
+            for item in items {
+                encode(item, out)?;
+            }
+        }
+        Value::Map(map) => {
+            let mut entries = Vec::with_capacity(map.len());
+            for (key, value) in map {
+                match key {
+                    Value::Text(key) => entries.push((key, value)),
+                    _ => return Err(invalid("map keys must be strings")),
+                }
+            }
+            entries.sort_by(|(a, _), (b, _)| {
+                a.len()
+                    .cmp(&b.len())
+                    .then_with(|| a.as_bytes().cmp(b.as_bytes()))
+            });
+            write_head(out, MAJOR_MAP, entries.len() as u64);
+            for (key, value) in entries {
+                write_head(out, MAJOR_TEXT, key.len() as u64);
+                out.extend_from_slice(key.as_bytes());
+                encode(value, out)?;
+            }
+        }
+        Value::Tag(CID_TAG, _) => {
+            let cid = parse_link(value)?;
+            let cid_bytes = cid.to_bytes();
+            write_head(out, MAJOR_TAG, CID_TAG);
+            write_head(out, MAJOR_BYTES, cid_bytes.len() as u64 + 1);
+            out.push(0);
+            out.extend_from_slice(&cid_bytes);
+        }
+        Value::Tag(tag, _) => return Err(invalid(&format!("unsupported tag {tag}"))),
+        _ => return Err(invalid("unsupported value")),
+    }
+    Ok(())
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: u64) -> Result<&'a [u8]> {
+        let len = usize::try_from(len).map_err(|_| invalid("length out of range"))?;
+        if self.buf.len() < len {
+            return Err(invalid("unexpected end of input"));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    /// Reads an initial byte and its argument, returning `(major, info, argument)`.
+    fn head(&mut self) -> Result<(u8, u8, u64)> {
+        let first = self.take(1)?[0];
+        let major = first >> 5;
+        let info = first & 0x1f;
+        if major == MAJOR_SIMPLE {
+            return Ok((major, info, 0));
+        }
+        let argument = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(self.take(1)?[0]),
+            25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().unwrap())),
+            26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().unwrap())),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            _ => return Err(invalid("indefinite lengths are not allowed")),
+        };
+        Ok((major, info, argument))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nesting too deep"));
+        }
+        let (major, info, argument) = self.head()?;
+        let value = match major {
+            MAJOR_UNSIGNED => Value::Integer(i128::from(argument)),
+            MAJOR_NEGATIVE => Value::Integer(-1 - i128::from(argument)),
+            MAJOR_BYTES => Value::Bytes(self.take(argument)?.to_vec()),
+            MAJOR_TEXT => {
+                let text = std::str::from_utf8(self.take(argument)?)
+                    .map_err(|_| invalid("text is not valid UTF-8"))?;
+                Value::Text(text.to_string())
+            }
+            MAJOR_ARRAY => {
+                let mut items = Vec::new();
+                for _ in 0..argument {
+                    items.push(self.value(depth + 1)?);
+                }
+                Value::Array(items)
+            }
+            MAJOR_MAP => {
+                let mut map = BTreeMap::new();
+                for _ in 0..argument {
+                    let key = match self.value(depth + 1)? {
+                        Value::Text(key) => key,
+                        _ => return Err(invalid("map keys must be strings")),
+                    };
+                    let value = self.value(depth + 1)?;
+                    if map.insert(Value::Text(key), value).is_some() {
+                        return Err(invalid("duplicate map key"));
+                    }
+                }
+                Value::Map(map)
+            }
+            MAJOR_TAG if argument == CID_TAG => {
+                let inner = self.value(depth + 1)?;
+                let link = Value::Tag(CID_TAG, Box::new(inner));
+                parse_link(&link)?;
+                link
+            }
+            MAJOR_TAG => return Err(invalid(&format!("unsupported tag {argument}"))),
+            _ => match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 => Value::Null,
+                27 => Value::Float(f64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+                _ => return Err(invalid("unsupported simple value or float width")),
+            },
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dasl::cid::ContentId;
+
+    fn text(value: &str) -> Value {
+        Value::Text(value.to_string())
+    }
+
+    #[test]
+    fn test_map_keys_are_sorted_length_first() {
+        let mut map = BTreeMap::new();
+        map.insert(text("bb"), Value::Integer(1));
+        map.insert(text("a"), Value::Integer(2));
+        map.insert(text("ab"), Value::Integer(3));
+
+        let bytes = to_vec(&Value::Map(map)).unwrap();
+        assert_eq!(
+            bytes,
+            [0xa3, 0x61, b'a', 0x02, 0x62, b'a', b'b', 0x03, 0x62, b'b', b'b', 0x01]
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_with_links() {
+        let cid = ContentId::new(b"linked").unwrap().0;
+        let mut map = BTreeMap::new();
+        map.insert(text("link"), link(&cid));
+        map.insert(text("n"), Value::Integer(-300));
+        map.insert(text("f"), Value::Float(1.5));
+        map.insert(
+            text("list"),
+            Value::Array(vec![Value::Null, Value::Bool(true)]),
+        );
+        let value = Value::Map(map);
+
+        let bytes = to_vec(&value).unwrap();
+        let decoded = from_slice(&bytes).unwrap();
+        assert_eq!(to_vec(&decoded).unwrap(), bytes);
+
+        let Value::Map(decoded) = decoded else {
+            panic!("expected map");
+        };
+        assert_eq!(parse_link(&decoded[&text("link")]).unwrap(), cid);
+    }
+
+    #[test]
+    fn test_rejects_non_canonical_input() {
+        // Indefinite-length array.
+        assert!(from_slice(&[0x9f, 0x01, 0xff]).is_err());
+        // Non-string map key.
+        assert!(from_slice(&[0xa1, 0x01, 0x02]).is_err());
+        // Unsupported tag.
+        assert!(from_slice(&[0xc1, 0x01]).is_err());
+        // Trailing bytes.
+        assert!(from_slice(&[0x01, 0x02]).is_err());
+        // NaN cannot be encoded.
+        assert!(to_vec(&Value::Float(f64::NAN)).is_err());
+    }
+}
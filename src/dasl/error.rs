@@ -42,6 +42,13 @@ pub enum DaslError {
 
     #[error("content integrity verification failed")]
     IntegrityVerificationFailed,
+
+    // CAR (Content Addressable aRchive) errors
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid CAR data: {0}")]
+    Car(String),
 }
 
 #[derive(Error, Debug)]
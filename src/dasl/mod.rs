@@ -1,3 +1,5 @@
+pub mod car;
 pub mod cid;
+pub mod dag_cbor;
 pub mod error;
 pub mod node;
@@ -1,3 +1,4 @@
+use super::dag_cbor::{self, DAG_CBOR_CODE};
 use super::error::{DaslError, NodeValidationError, Result};
 use cid::Cid;
 use multihash::Multihash;
@@ -10,6 +11,42 @@ use std::collections::BTreeMap;
 const SHA2_256_CODE: u64 = 0x12;
 const RAW_CODE: u64 = 0x55;
 
+/// Encoding used to derive a node's CID.
+///
+/// The encoding is versioned so that stores written before DAG-CBOR support keep
+/// verifying: their nodes decode as [`NodeEncoding::Cbor`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeEncoding {
+    /// Original encoding: `serde_cbor` bytes addressed with the raw codec (0x55).
+    #[default]
+    Cbor,
+    /// Canonical DAG-CBOR with tag 42 links, addressed with the dag-cbor codec (0x71).
+    DagCbor,
+}
+
+impl NodeEncoding {
+    /// Returns the multicodec code of CIDs produced with this encoding.
+    pub fn codec(&self) -> u64 {
+        match self {
+            NodeEncoding::Cbor => RAW_CODE,
+            NodeEncoding::DagCbor => DAG_CBOR_CODE,
+        }
+    }
+
+    /// Returns the encoding matching a CID codec, if any.
+    pub fn from_codec(codec: u64) -> Option<Self> {
+        match codec {
+            RAW_CODE => Some(NodeEncoding::Cbor),
+            DAG_CBOR_CODE => Some(NodeEncoding::DagCbor),
+            _ => None,
+        }
+    }
+
+    fn is_cbor(&self) -> bool {
+        *self == NodeEncoding::Cbor
+    }
+}
+
 /// This structure can store any type of payload data and metadata, making it versatile for various use cases.
 ///
 /// # Type Parameters
@@ -23,6 +60,8 @@ const RAW_CODE: u64 = 0x55;
 /// * `genesis` - The genesis CID that this node belongs to (None for genesis nodes, Some(genesis_cid) for child nodes).
 /// * `timestamp` - Unix timestamp in nanoseconds representing when the entry was created.
 /// * `metadata` - Additional information about the entry (e.g., author, tags, or other attributes).
+/// * `encoding` - How the CID is derived, see [`NodeEncoding`]. Nodes in the
+///   original `Cbor` encoding serialize without this field.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(bound = "P: Serialize + for<'a> Deserialize<'a>, M: Serialize + for<'a> Deserialize<'a>")]
 pub struct Node<P, M = BTreeMap<String, String>> {
@@ -31,6 +70,8 @@ pub struct Node<P, M = BTreeMap<String, String>> {
     pub genesis: Option<Cid>,
     pub timestamp: u64,
     pub metadata: M,
+    #[serde(default, skip_serializing_if = "NodeEncoding::is_cbor")]
+    pub encoding: NodeEncoding,
 }
 
 impl<P, M> Node<P, M>
@@ -46,6 +87,7 @@ where
             genesis: None,
             timestamp,
             metadata,
+            encoding: NodeEncoding::default(),
         }
     }
 
@@ -63,6 +105,7 @@ where
             genesis: Some(genesis),
             timestamp,
             metadata,
+            encoding: NodeEncoding::default(),
         }
     }
 
@@ -74,10 +117,92 @@ where
     /// # Errors
     /// Returns a NodeError if serialization or hashing fails
     pub fn content_id(&self) -> Result<Cid> {
-        let buf = self.to_bytes()?;
+        let buf = self.to_canonical_bytes()?;
         let hash = Sha256::digest(&buf);
         let mh = Multihash::<64>::wrap(SHA2_256_CODE, &hash)?;
-        Ok(Cid::new_v1(RAW_CODE, mh))
+        Ok(Cid::new_v1(self.encoding.codec(), mh))
+    }
+
+    /// Returns the node with the given CID encoding.
+    pub fn with_encoding(mut self, encoding: NodeEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Serializes this node into the bytes its CID is computed over
+    ///
+    /// For [`NodeEncoding::Cbor`] this is the same as [`Self::to_bytes`]. For
+    /// [`NodeEncoding::DagCbor`] it is canonical DAG-CBOR where `parents` and
+    /// `genesis` are tag 42 links.
+    ///
+    /// # Errors
+    /// Returns a NodeError if serialization fails
+    pub fn to_canonical_bytes(&self) -> Result<Vec<u8>> {
+        match self.encoding {
+            NodeEncoding::Cbor => self.to_bytes(),
+            NodeEncoding::DagCbor => {
+                let value = serde_cbor::value::to_value(self)?;
+                let serde_cbor::Value::Map(mut fields) = value else {
+                    return Err(DaslError::Deserialization {
+                        message: "node did not serialize to a map".to_string(),
+                    });
+                };
+                fields.remove(&text_key("encoding"));
+                if let Some(parents) = fields.get_mut(&text_key("parents")) {
+                    *parents =
+                        serde_cbor::Value::Array(self.parents.iter().map(dag_cbor::link).collect());
+                }
+                if let (Some(genesis), Some(cid)) =
+                    (fields.get_mut(&text_key("genesis")), self.genesis.as_ref())
+                {
+                    *genesis = dag_cbor::link(cid);
+                }
+                dag_cbor::to_vec(&serde_cbor::Value::Map(fields))
+            }
+        }
+    }
+
+    /// Deserializes a node from the bytes its CID is computed over
+    ///
+    /// # Arguments
+    /// * `buf` - Bytes produced by [`Self::to_canonical_bytes`]
+    /// * `codec` - Codec of the node's CID, selecting the encoding
+    ///
+    /// # Errors
+    /// Returns a NodeError if the codec is unknown or deserialization fails
+    pub fn from_canonical_bytes(buf: &[u8], codec: u64) -> Result<Self> {
+        match NodeEncoding::from_codec(codec) {
+            Some(NodeEncoding::Cbor) => Self::from_bytes(buf),
+            Some(NodeEncoding::DagCbor) => {
+                let serde_cbor::Value::Map(mut fields) = dag_cbor::from_slice(buf)? else {
+                    return Err(DaslError::Deserialization {
+                        message: "DAG-CBOR node is not a map".to_string(),
+                    });
+                };
+                if let Some(serde_cbor::Value::Array(parents)) =
+                    fields.get_mut(&text_key("parents"))
+                {
+                    for parent in parents.iter_mut() {
+                        *parent =
+                            serde_cbor::Value::Bytes(dag_cbor::parse_link(parent)?.to_bytes());
+                    }
+                }
+                if let Some(genesis) = fields.get_mut(&text_key("genesis")) {
+                    if !matches!(genesis, serde_cbor::Value::Null) {
+                        *genesis =
+                            serde_cbor::Value::Bytes(dag_cbor::parse_link(genesis)?.to_bytes());
+                    }
+                }
+                let node: Self = serde_cbor::value::from_value(serde_cbor::Value::Map(fields))
+                    .map_err(|e| DaslError::Deserialization {
+                        message: format!("Failed to deserialize node: {e}"),
+                    })?;
+                Ok(node.with_encoding(NodeEncoding::DagCbor))
+            }
+            None => Err(DaslError::InvalidCid(format!(
+                "unsupported node codec: {codec:#x}"
+            ))),
+        }
     }
 
     /// Serializes this node using CBOR
@@ -152,6 +277,10 @@ where
     }
 }
 
+fn text_key(key: &str) -> serde_cbor::Value {
+    serde_cbor::Value::Text(key.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -404,4 +533,111 @@ mod tests {
         assert_eq!(node.parents()[2], parent3);
         assert_eq!(node.parents()[3], parent4);
     }
+
+    #[test]
+    fn test_cbor_encoding_keeps_existing_content_ids() {
+        let node = Node::new_genesis(
+            "hello".to_string(),
+            1234567890,
+            BTreeMap::<String, String>::new(),
+        );
+        let cid = node.content_id().unwrap();
+
+        assert_eq!(
+            cid.to_string(),
+            "bafkreihfxmlaoorbnb6ws2orckx3wm5az4jbtizh6tj2snuzwwtw6ldh3e"
+        );
+        assert_eq!(node.to_canonical_bytes().unwrap(), node.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_dag_cbor_content_id_and_roundtrip() {
+        let genesis_cid = create_test_content_id(b"genesis");
+        let parent = create_test_content_id(b"parent");
+        let node = Node::new_child(
+            "test".to_string(),
+            vec![parent],
+            genesis_cid,
+            1234567890,
+            BTreeMap::<String, String>::new(),
+        )
+        .with_encoding(NodeEncoding::DagCbor);
+
+        let cid = node.content_id().unwrap();
+        assert_eq!(cid.codec(), DAG_CBOR_CODE);
+        assert!(node.verify_self_integrity(&cid).unwrap());
+
+        let canonical = node.to_canonical_bytes().unwrap();
+        let decoded: Node<String, BTreeMap<String, String>> =
+            Node::from_canonical_bytes(&canonical, cid.codec()).unwrap();
+        assert_eq!(decoded, node);
+
+        // The storage form carries the encoding so the CID can be recomputed.
+        let stored: Node<String, BTreeMap<String, String>> =
+            Node::from_bytes(&node.to_bytes().unwrap()).unwrap();
+        assert_eq!(stored.encoding, NodeEncoding::DagCbor);
+        assert_eq!(stored.content_id().unwrap(), cid);
+    }
+
+    #[test]
+    fn test_dag_cbor_encodes_links_and_sorted_keys() {
+        let genesis_cid = create_test_content_id(b"genesis");
+        let parent = create_test_content_id(b"parent");
+        let node = Node::new_child(
+            "test".to_string(),
+            vec![parent],
+            genesis_cid,
+            1,
+            BTreeMap::<String, String>::new(),
+        )
+        .with_encoding(NodeEncoding::DagCbor);
+
+        let canonical = node.to_canonical_bytes().unwrap();
+        let serde_cbor::Value::Map(fields) = dag_cbor::from_slice(&canonical).unwrap() else {
+            panic!("expected map");
+        };
+        assert!(!fields.contains_key(&text_key("encoding")));
+        assert_eq!(
+            dag_cbor::parse_link(&fields[&text_key("genesis")]).unwrap(),
+            genesis_cid
+        );
+        let serde_cbor::Value::Array(parents) = &fields[&text_key("parents")] else {
+            panic!("expected parents array");
+        };
+        assert_eq!(dag_cbor::parse_link(&parents[0]).unwrap(), parent);
+        assert_eq!(
+            dag_cbor::to_vec(&serde_cbor::Value::Map(fields)).unwrap(),
+            canonical
+        );
+    }
+
+    #[test]
+    fn test_dag_cbor_is_independent_of_map_order() {
+        use std::collections::HashMap;
+
+        let keys: Vec<String> = (0..32).map(|i| format!("key-{i}")).collect();
+        let mut forward = HashMap::new();
+        for key in &keys {
+            forward.insert(key.clone(), key.len());
+        }
+        let mut backward = HashMap::new();
+        for key in keys.iter().rev() {
+            backward.insert(key.clone(), key.len());
+        }
+
+        let a =
+            Node::new_genesis("doc".to_string(), 1, forward).with_encoding(NodeEncoding::DagCbor);
+        let b =
+            Node::new_genesis("doc".to_string(), 1, backward).with_encoding(NodeEncoding::DagCbor);
+        assert_eq!(a.content_id().unwrap(), b.content_id().unwrap());
+    }
+
+    #[test]
+    fn test_from_canonical_bytes_rejects_unknown_codec() {
+        let node = Node::new_genesis("x".to_string(), 1, BTreeMap::<String, String>::new());
+        let bytes = node.to_bytes().unwrap();
+        assert!(
+            Node::<String, BTreeMap<String, String>>::from_canonical_bytes(&bytes, 0x70).is_err()
+        );
+    }
 }
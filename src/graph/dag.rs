@@ -1,4 +1,4 @@
-use crate::dasl::node::Node;
+use crate::dasl::node::{Node, NodeEncoding};
 use crate::graph::error::{GraphError, Result};
 use crate::graph::storage::NodeStorage;
 use cid::Cid;
@@ -19,6 +19,9 @@ where
 {
     pub storage: S,
     edges_forward: HashMap<Cid, Vec<Cid>>, // parent -> children
+    heads: HashMap<Cid, Vec<Cid>>,         // genesis -> current heads
+    pending_heads: HashMap<Cid, (Cid, Vec<Cid>)>, // staged node -> (genesis, previous heads)
+    node_encoding: NodeEncoding,
     _p_marker: PhantomData<P>,
     _m_marker: PhantomData<M>,
 }
@@ -33,11 +36,26 @@ where
         Self {
             storage,
             edges_forward: HashMap::new(),
+            heads: HashMap::new(),
+            pending_heads: HashMap::new(),
+            node_encoding: NodeEncoding::default(),
             _p_marker: PhantomData,
             _m_marker: PhantomData,
         }
     }
 
+    /// Selects the encoding used to derive CIDs of newly created nodes.
+    ///
+    /// Existing nodes keep the encoding their CID was created with.
+    pub fn with_node_encoding(mut self, encoding: NodeEncoding) -> Self {
+        self.node_encoding = encoding;
+        self
+    }
+
+    pub fn node_encoding(&self) -> NodeEncoding {
+        self.node_encoding
+    }
+
     /// Adds a node to the DAG with a specified timestamp.
     ///
     /// If parents is empty, creates a genesis node. Otherwise, creates a child node.
@@ -137,7 +155,8 @@ where
         timestamp: u64,
         metadata: M,
     ) -> Result<(Cid, Node<P, M>)> {
-        let node = Node::new_genesis(payload, timestamp, metadata);
+        let node =
+            Node::new_genesis(payload, timestamp, metadata).with_encoding(self.node_encoding);
         let cid = node.content_id()?;
         Ok((cid, node))
     }
@@ -163,7 +182,8 @@ where
         timestamp: u64,
         metadata: M,
     ) -> Result<(Cid, Node<P, M>)> {
-        let node = Node::new_child(payload, parents.clone(), genesis, timestamp, metadata);
+        let node = Node::new_child(payload, parents.clone(), genesis, timestamp, metadata)
+            .with_encoding(self.node_encoding);
         let cid = node.content_id()?;
 
         if self.would_create_cycle_with(&cid, &parents)? {
@@ -177,24 +197,54 @@ where
     fn persist_and_cache(&mut self, cid: Cid, node: Node<P, M>) -> Result<Cid> {
         self.storage.put(&node)?;
         self.register_prepared_node(cid, &node)?;
+        self.finalize_pending_node(&cid);
         Ok(cid)
     }
 
+    /// Registers a node that has been written to storage (possibly inside a batch).
+    ///
+    /// Updates the adjacency cache and the head set of the node's genesis: the node
+    /// becomes a head and its parents stop being heads. The new head set is persisted
+    /// through the storage (and therefore joins an active batch). The previous head set
+    /// is remembered until [`Self::finalize_pending_node`] or
+    /// [`Self::rollback_pending_node`] is called.
     pub fn register_prepared_node(&mut self, cid: Cid, node: &Node<P, M>) -> Result<()> {
         let parents = node.parents();
-        if parents.is_empty() {
-            self.edges_forward.entry(cid).or_default();
-            return Ok(());
+        if !parents.is_empty() {
+            self.ensure_subgraph_cached(parents)?;
         }
 
-        self.ensure_subgraph_cached(parents)?;
+        let genesis = node.genesis.unwrap_or(cid);
+        // The node itself is already stored, so a head scan may list it.
+        let mut previous = self.heads(&genesis)?;
+        previous.retain(|head| head != &cid);
+        let mut next: Vec<Cid> = previous
+            .iter()
+            .copied()
+            .filter(|head| !parents.contains(head))
+            .collect();
+        if !next.contains(&cid) {
+            next.push(cid);
+        }
+        // Persist first, so a failure leaves the caches untouched.
+        self.storage.put_heads(&genesis, &next)?;
+
         for &parent in parents {
             self.edges_forward.entry(parent).or_default().push(cid);
         }
         self.edges_forward.entry(cid).or_default();
+        self.heads.insert(genesis, next);
+        self.pending_heads.insert(cid, (genesis, previous));
         Ok(())
     }
 
+    /// Forgets the rollback information of a staged node once its batch is committed.
+    pub fn finalize_pending_node(&mut self, cid: &Cid) {
+        self.pending_heads.remove(cid);
+    }
+
+    /// Reverts the cache updates made by [`Self::register_prepared_node`] and restores
+    /// the head set that was current before the node was staged.
     pub fn rollback_pending_node(&mut self, cid: &Cid, parents: &[Cid]) {
         if let Some(children) = self.edges_forward.get_mut(cid) {
             children.clear();
@@ -206,6 +256,16 @@ where
                 children.retain(|child| child != cid);
             }
         }
+
+        if let Some((genesis, previous)) = self.pending_heads.remove(cid) {
+            // Inside a batch this write is discarded together with the batch; without a
+            // batch it undoes the head update that was written directly.
+            if self.storage.put_heads(&genesis, &previous).is_err() {
+                self.heads.remove(&genesis);
+            } else {
+                self.heads.insert(genesis, previous);
+            }
+        }
     }
 
     pub fn remove_node(&mut self, cid: &Cid) -> Result<()> {
@@ -231,6 +291,12 @@ where
         self.edges_forward.remove(cid);
         self.storage.delete(cid)?;
 
+        let genesis = node.genesis.unwrap_or(*cid);
+        self.heads.remove(&genesis);
+        let heads = self.scan_heads(&genesis)?;
+        self.storage.put_heads(&genesis, &heads)?;
+        self.heads.insert(genesis, heads);
+
         Ok(())
     }
 
@@ -238,7 +304,18 @@ where
         self.storage.get(cid)
     }
 
+    /// Returns all node CIDs that belong to the given genesis, including the genesis itself.
+    ///
+    /// Uses the storage's genesis index when available and falls back to a full scan.
     pub fn get_nodes_by_genesis(&self, genesis_id: &Cid) -> Result<Vec<Cid>> {
+        if let Some(mut members) = self.storage.get_nodes_by_genesis(genesis_id)? {
+            // Preserve the scan semantics where the queried node itself is always included.
+            if !members.contains(genesis_id) && self.storage.get(genesis_id)?.is_some() {
+                members.push(*genesis_id);
+            }
+            return Ok(members);
+        }
+
         let mut result = Vec::new();
         let node_map = self.storage.get_node_map()?;
         for (cid, _) in node_map {
@@ -251,6 +328,87 @@ where
         Ok(result)
     }
 
+    /// Returns the CIDs of all genesis nodes in storage, sorted.
+    pub fn genesis_ids(&self) -> Result<Vec<Cid>> {
+        if let Some(geneses) = self.storage.get_genesis_ids()? {
+            return Ok(geneses);
+        }
+        let mut geneses: Vec<Cid> = self
+            .storage
+            .get_node_map()?
+            .into_iter()
+            .filter(|(_, parents)| parents.is_empty())
+            .map(|(cid, _)| cid)
+            .collect();
+        geneses.sort();
+        Ok(geneses)
+    }
+
+    /// Returns the given nodes and all of their ancestors in causal order.
+    ///
+    /// Every node appears after all of its parents. Nodes missing from storage are
+    /// skipped, so the result only contains locally known history.
+    pub fn ancestors(&self, from: &[Cid]) -> Result<Vec<Cid>> {
+        let mut ordered = Vec::new();
+        let mut visited = HashSet::new();
+        // (cid, parents_pushed)
+        let mut stack: Vec<(Cid, bool)> = from.iter().rev().map(|cid| (*cid, false)).collect();
+
+        while let Some((cid, expanded)) = stack.pop() {
+            if expanded {
+                ordered.push(cid);
+                continue;
+            }
+            if !visited.insert(cid) {
+                continue;
+            }
+            let Some(node) = self.storage.get(&cid)? else {
+                continue;
+            };
+            stack.push((cid, true));
+            for parent in node.parents().iter().rev() {
+                if !visited.contains(parent) {
+                    stack.push((*parent, false));
+                }
+            }
+        }
+
+        Ok(ordered)
+    }
+
+    /// Returns the lowest common ancestors of the given nodes, sorted.
+    ///
+    /// A node counts as its own ancestor, so when one node is an ancestor of all others
+    /// it is the result. Criss-cross histories can have several lowest common ancestors;
+    /// nodes of unrelated genesis series have none.
+    pub fn common_ancestors(&self, nodes: &[Cid]) -> Result<Vec<Cid>> {
+        let Some((first, rest)) = nodes.split_first() else {
+            return Ok(Vec::new());
+        };
+
+        let mut common: HashSet<Cid> = self.ancestors(&[*first])?.into_iter().collect();
+        for node in rest {
+            let reachable: HashSet<Cid> = self.ancestors(&[*node])?.into_iter().collect();
+            common.retain(|cid| reachable.contains(cid));
+        }
+
+        // Every ancestor of a common ancestor is common too, so a common node is
+        // dominated exactly when it is the parent of another common node.
+        let mut dominated = HashSet::new();
+        for cid in &common {
+            if let Some(node) = self.storage.get(cid)? {
+                dominated.extend(node.parents().iter().copied());
+            }
+        }
+
+        let mut lowest: Vec<Cid> = common
+            .into_iter()
+            .filter(|cid| !dominated.contains(cid))
+            .collect();
+        lowest.sort();
+        Ok(lowest)
+    }
+
     /// Check if adding an edge (new node with parents) would create a cycle
     fn would_create_cycle_with(&mut self, new_cid: &Cid, parents: &[Cid]) -> Result<bool> {
         // Build cache only for the relevant subgraph
@@ -432,6 +590,30 @@ where
         }
     }
 
+    /// Returns the current heads (leaf nodes) of a genesis.
+    ///
+    /// Served from the in-memory cache or the persisted head set; genesis series that
+    /// predate head tracking fall back to scanning their members.
+    pub fn heads(&self, genesis_id: &Cid) -> Result<Vec<Cid>> {
+        if let Some(heads) = self.heads.get(genesis_id) {
+            return Ok(heads.clone());
+        }
+        if let Some(heads) = self.storage.get_heads(genesis_id)? {
+            return Ok(heads);
+        }
+        self.scan_heads(genesis_id)
+    }
+
+    /// Recomputes the heads of a genesis by inspecting every member node.
+    fn scan_heads(&self, genesis_id: &Cid) -> Result<Vec<Cid>> {
+        let nodes = self.get_nodes_by_genesis(genesis_id)?;
+        let has_children = self.collect_nodes_with_children(&nodes)?;
+        Ok(nodes
+            .into_iter()
+            .filter(|cid| !has_children.contains(cid))
+            .collect())
+    }
+
     /// Calculates the latest node CID for a given genesis ID by finding the leaf node(s) with the most recent timestamp.
     ///
     /// # Arguments
@@ -446,15 +628,11 @@ where
     ///
     /// Returns an error if node retrieval fails or an internal error occurs.
     pub fn calculate_latest(&self, genesis_id: &Cid) -> Result<Option<Cid>> {
-        let nodes = self.get_nodes_by_genesis(genesis_id)?;
-        if nodes.is_empty() {
-            return Ok(None);
+        let heads = self.heads(genesis_id)?;
+        if heads.len() <= 1 {
+            return Ok(heads.first().copied());
         }
-        if nodes.len() == 1 {
-            return Ok(Some(nodes[0]));
-        }
-        let has_children = self.collect_nodes_with_children(&nodes)?;
-        let mut leaf_nodes = self.collect_leaf_nodes(&nodes, &has_children)?;
+        let mut leaf_nodes = self.collect_timestamps(&heads)?;
         leaf_nodes.sort_by_key(|(_, timestamp)| std::cmp::Reverse(*timestamp));
         Ok(leaf_nodes.first().map(|(cid, _)| *cid))
     }
@@ -474,21 +652,15 @@ where
         Ok(has_children)
     }
 
-    // Returns a list of leaf nodes (nodes without children) and their timestamps among the given versions.
-    fn collect_leaf_nodes(
-        &self,
-        nodes: &[Cid],
-        has_children: &HashSet<Cid>,
-    ) -> Result<Vec<(Cid, u64)>> {
-        let mut leaf_nodes = Vec::new();
+    // Returns the given nodes paired with their timestamps, skipping nodes that cannot be found.
+    fn collect_timestamps(&self, nodes: &[Cid]) -> Result<Vec<(Cid, u64)>> {
+        let mut timestamps = Vec::with_capacity(nodes.len());
         for &node_cid in nodes {
-            if !has_children.contains(&node_cid) {
-                if let Some(node) = self.storage.get(&node_cid)? {
-                    leaf_nodes.push((node_cid, node.timestamp()));
-                }
+            if let Some(node) = self.storage.get(&node_cid)? {
+                timestamps.push((node_cid, node.timestamp()));
             }
         }
-        Ok(leaf_nodes)
+        Ok(timestamps)
     }
 }
 
@@ -506,12 +678,14 @@ mod tests {
     struct MockStorage {
         edges: Mutex<HashMap<Cid, Vec<Cid>>>,
         timestamps: Mutex<HashMap<Cid, u64>>,
+        fail_put_heads: Mutex<bool>,
     }
     impl MockStorage {
         fn new() -> Self {
             Self {
                 edges: Mutex::new(HashMap::new()),
                 timestamps: Mutex::new(HashMap::new()),
+                fail_put_heads: Mutex::new(false),
             }
         }
 
@@ -592,6 +766,13 @@ mod tests {
         fn get_node_map(&self) -> Result<HashMap<Cid, Vec<Cid>>> {
             Ok(self.edges.lock().unwrap().clone())
         }
+
+        fn put_heads(&self, _genesis: &Cid, _heads: &[Cid]) -> Result<()> {
+            if *self.fail_put_heads.lock().unwrap() {
+                return Err(GraphError::NodeOperation("forced failure".to_string()));
+            }
+            Ok(())
+        }
     }
 
     fn create_test_content_id(data: &[u8]) -> Cid {
@@ -1145,4 +1326,254 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_register_leaves_caches_untouched_when_heads_fail() {
+        let mut dag = TestDag::new(MockStorage::new());
+        let genesis = dag
+            .add_genesis_node("root".to_string(), 1000, BTreeMap::new())
+            .unwrap();
+        let (child_cid, child_node) = dag
+            .prepare_child_node(
+                "child".to_string(),
+                vec![genesis],
+                genesis,
+                2000,
+                BTreeMap::new(),
+            )
+            .unwrap();
+
+        *dag.storage.fail_put_heads.lock().unwrap() = true;
+        assert!(dag.register_prepared_node(child_cid, &child_node).is_err());
+        assert!(!dag.edges_forward.contains_key(&child_cid));
+        assert!(dag.edges_forward[&genesis].is_empty());
+        assert_eq!(dag.heads(&genesis).unwrap(), vec![genesis]);
+    }
+
+    #[test]
+    fn test_heads_are_maintained_and_persisted() {
+        let temp_dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, BTreeMap<String, String>>::open(temp_dir.path());
+        let mut dag = DagGraph::new(storage.clone());
+
+        let genesis = dag
+            .add_genesis_node("root".to_string(), 1000, BTreeMap::new())
+            .unwrap();
+        let branch_a = dag
+            .add_child_node(
+                "a".to_string(),
+                vec![genesis],
+                genesis,
+                2000,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        let branch_b = dag
+            .add_child_node(
+                "b".to_string(),
+                vec![genesis],
+                genesis,
+                3000,
+                BTreeMap::new(),
+            )
+            .unwrap();
+
+        let mut heads = dag.heads(&genesis).unwrap();
+        heads.sort();
+        let mut expected = vec![branch_a, branch_b];
+        expected.sort();
+        assert_eq!(heads, expected);
+
+        let merge = dag
+            .add_child_node(
+                "merge".to_string(),
+                vec![branch_a, branch_b],
+                genesis,
+                4000,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        assert_eq!(dag.heads(&genesis).unwrap(), vec![merge]);
+
+        // A fresh graph over the same storage reads the persisted head set.
+        let reopened = DagGraph::<_, String, BTreeMap<String, String>>::new(storage.clone());
+        assert_eq!(storage.get_heads(&genesis).unwrap(), Some(vec![merge]));
+        assert_eq!(reopened.calculate_latest(&genesis).unwrap(), Some(merge));
+    }
+
+    #[test]
+    fn test_ancestors_are_in_causal_order() {
+        let dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, BTreeMap<String, String>>::open(dir.path());
+        let mut dag = DagGraph::new(storage);
+
+        let genesis = dag
+            .add_genesis_node("root".to_string(), 1, BTreeMap::new())
+            .unwrap();
+        let left = dag
+            .add_child_node(
+                "left".to_string(),
+                vec![genesis],
+                genesis,
+                2,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        let right = dag
+            .add_child_node(
+                "right".to_string(),
+                vec![genesis],
+                genesis,
+                3,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        let merge = dag
+            .add_child_node(
+                "merge".to_string(),
+                vec![left, right],
+                genesis,
+                4,
+                BTreeMap::new(),
+            )
+            .unwrap();
+
+        let ordered = dag.ancestors(&[merge]).unwrap();
+        assert_eq!(ordered.len(), 4);
+        let position = |cid: &Cid| ordered.iter().position(|c| c == cid).unwrap();
+        assert!(position(&genesis) < position(&left));
+        assert!(position(&genesis) < position(&right));
+        assert!(position(&left) < position(&merge));
+        assert!(position(&right) < position(&merge));
+
+        assert_eq!(dag.ancestors(&[left]).unwrap(), vec![genesis, left]);
+        assert_eq!(dag.genesis_ids().unwrap(), vec![genesis]);
+    }
+
+    #[test]
+    fn test_common_ancestors() {
+        let dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, BTreeMap<String, String>>::open(dir.path());
+        let mut dag = DagGraph::new(storage);
+
+        //        genesis
+        //        /     \
+        //      left   right
+        //       | \   / |
+        //       |  \ /  |
+        //       |   X   |
+        //       |  / \  |
+        //     cross_a  cross_b
+        let genesis = dag
+            .add_genesis_node("root".to_string(), 1, BTreeMap::new())
+            .unwrap();
+        let left = dag
+            .add_child_node(
+                "left".to_string(),
+                vec![genesis],
+                genesis,
+                2,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        let right = dag
+            .add_child_node(
+                "right".to_string(),
+                vec![genesis],
+                genesis,
+                3,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        let cross_a = dag
+            .add_child_node(
+                "cross-a".to_string(),
+                vec![left, right],
+                genesis,
+                4,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        let cross_b = dag
+            .add_child_node(
+                "cross-b".to_string(),
+                vec![right, left],
+                genesis,
+                5,
+                BTreeMap::new(),
+            )
+            .unwrap();
+
+        assert_eq!(dag.common_ancestors(&[left, right]).unwrap(), vec![genesis]);
+        assert_eq!(dag.common_ancestors(&[left, cross_a]).unwrap(), vec![left]);
+        assert_eq!(dag.common_ancestors(&[cross_a]).unwrap(), vec![cross_a]);
+
+        let mut expected = vec![left, right];
+        expected.sort();
+        assert_eq!(dag.common_ancestors(&[cross_a, cross_b]).unwrap(), expected);
+
+        let other = dag
+            .add_genesis_node("other".to_string(), 6, BTreeMap::new())
+            .unwrap();
+        assert!(dag.common_ancestors(&[left, other]).unwrap().is_empty());
+        assert!(dag.common_ancestors(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_dag_cbor_nodes_persist_and_reload() {
+        let dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, BTreeMap<String, String>>::open(dir.path());
+        let mut dag = DagGraph::new(storage).with_node_encoding(NodeEncoding::DagCbor);
+
+        let genesis = dag
+            .add_genesis_node("root".to_string(), 1, BTreeMap::new())
+            .unwrap();
+        let child = dag
+            .add_child_node(
+                "child".to_string(),
+                vec![genesis],
+                genesis,
+                2,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        assert_eq!(genesis.codec(), crate::dasl::dag_cbor::DAG_CBOR_CODE);
+
+        let node = dag.get_node(&child).unwrap().unwrap();
+        assert_eq!(node.encoding, NodeEncoding::DagCbor);
+        assert!(node.verify_self_integrity(&child).unwrap());
+        assert_eq!(dag.genesis_ids().unwrap(), vec![genesis]);
+        assert_eq!(dag.heads(&genesis).unwrap(), vec![child]);
+    }
+
+    #[test]
+    fn test_rollback_pending_node_restores_heads() {
+        let temp_dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, BTreeMap<String, String>>::open(temp_dir.path());
+        let mut dag = DagGraph::new(storage);
+
+        let genesis = dag
+            .add_genesis_node("root".to_string(), 1000, BTreeMap::new())
+            .unwrap();
+        let (child_cid, child_node) = dag
+            .prepare_child_node(
+                "child".to_string(),
+                vec![genesis],
+                genesis,
+                2000,
+                BTreeMap::new(),
+            )
+            .unwrap();
+        dag.storage.put(&child_node).unwrap();
+        dag.register_prepared_node(child_cid, &child_node).unwrap();
+        assert_eq!(dag.heads(&genesis).unwrap(), vec![child_cid]);
+
+        dag.rollback_pending_node(&child_cid, child_node.parents());
+
+        assert_eq!(dag.heads(&genesis).unwrap(), vec![genesis]);
+        assert_eq!(
+            dag.storage.get_heads(&genesis).unwrap(),
+            Some(vec![genesis])
+        );
+    }
 }
@@ -3,16 +3,51 @@ use crate::graph::error::{GraphError, Result};
 use crate::storage::{SharedLeveldb, SharedLeveldbAccess};
 use cid::Cid;
 use rusty_leveldb::LdbIterator;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 use std::sync::Arc;
 
+/// LevelDB namespace holding serialized nodes keyed by their CID.
+const NODE_PREFIX: u8 = 0x10;
+/// LevelDB namespace for the `genesis → member` index.
+const GENESIS_INDEX_PREFIX: u8 = 0x11;
+/// LevelDB namespace for the persisted head set of each genesis.
+const HEADS_PREFIX: u8 = 0x12;
+/// Marker recording that the genesis index has been built for this store.
+const GENESIS_INDEX_MARKER: &[u8] = b"\x00meta/nodes-by-genesis/v1";
+
 /// Minimal interface required for persisting DAG nodes.
 pub trait NodeStorage<P, M>: Send + Sync {
     fn get(&self, content_id: &Cid) -> Result<Option<Node<P, M>>>;
     fn put(&self, node: &Node<P, M>) -> Result<()>;
     fn delete(&self, content_id: &Cid) -> Result<()>;
     fn get_node_map(&self) -> Result<HashMap<Cid, Vec<Cid>>>;
+
+    /// Returns every node CID belonging to `genesis` (including the genesis itself).
+    ///
+    /// Backends without a genesis index return `Ok(None)`, in which case callers
+    /// fall back to scanning [`NodeStorage::get_node_map`].
+    fn get_nodes_by_genesis(&self, _genesis: &Cid) -> Result<Option<Vec<Cid>>> {
+        Ok(None)
+    }
+
+    /// Returns the CIDs of all genesis nodes, sorted.
+    ///
+    /// Backends without a genesis index return `Ok(None)`, in which case callers
+    /// fall back to scanning [`NodeStorage::get_node_map`].
+    fn get_genesis_ids(&self) -> Result<Option<Vec<Cid>>> {
+        Ok(None)
+    }
+
+    /// Returns the persisted head set of `genesis`, or `Ok(None)` when none is stored.
+    fn get_heads(&self, _genesis: &Cid) -> Result<Option<Vec<Cid>>> {
+        Ok(None)
+    }
+
+    /// Persists the head set of `genesis`. Backends without head tracking ignore the call.
+    fn put_heads(&self, _genesis: &Cid, _heads: &[Cid]) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// [`NodeStorage`] implementation backed by a shared LevelDB instance.
@@ -48,11 +83,38 @@ impl<P, M> LeveldbNodeStorage<P, M> {
     /// Builds the LevelDB key for nodes, prefixed with the `0x10` namespace.
     fn make_key(cid: &Cid) -> Vec<u8> {
         let mut v = Vec::with_capacity(1 + cid.to_bytes().len());
-        v.push(0x10);
+        v.push(NODE_PREFIX);
         v.extend_from_slice(&cid.to_bytes());
         v
     }
 
+    /// Builds the index prefix shared by all members of a genesis (`0x11` namespace).
+    ///
+    /// CID bytes are self-delimiting, so one genesis prefix never matches another.
+    fn make_index_prefix(genesis: &Cid) -> Vec<u8> {
+        let genesis_bytes = genesis.to_bytes();
+        let mut v = Vec::with_capacity(1 + genesis_bytes.len());
+        v.push(GENESIS_INDEX_PREFIX);
+        v.extend_from_slice(&genesis_bytes);
+        v
+    }
+
+    /// Builds the LevelDB key for the head set of a genesis (`0x12` namespace).
+    fn make_heads_key(genesis: &Cid) -> Vec<u8> {
+        let genesis_bytes = genesis.to_bytes();
+        let mut v = Vec::with_capacity(1 + genesis_bytes.len());
+        v.push(HEADS_PREFIX);
+        v.extend_from_slice(&genesis_bytes);
+        v
+    }
+
+    /// Builds the index key `0x11 | genesis | member`.
+    fn make_index_key(genesis: &Cid, member: &Cid) -> Vec<u8> {
+        let mut v = Self::make_index_prefix(genesis);
+        v.extend_from_slice(&member.to_bytes());
+        v
+    }
+
     /// Writes either into the active batch, or directly into the DB if no batch is active.
     fn write_bytes(&self, key: &[u8], value: &[u8]) -> Result<()> {
         if self
@@ -81,6 +143,53 @@ impl<P, M> LeveldbNodeStorage<P, M> {
     }
 }
 
+impl<P, M> LeveldbNodeStorage<P, M>
+where
+    P: serde::Serialize + for<'de> serde::Deserialize<'de>,
+    M: serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+    /// Builds the genesis index for nodes written before the index existed.
+    ///
+    /// Scans the node namespace once and writes all index entries together with a
+    /// completion marker in a single LevelDB write. Subsequent calls are no-ops.
+    /// Returns the number of indexed nodes.
+    pub fn migrate_genesis_index(&self) -> Result<usize> {
+        if self.shared.db().get(GENESIS_INDEX_MARKER).is_some() {
+            return Ok(0);
+        }
+
+        let mut batch = rusty_leveldb::WriteBatch::default();
+        let mut indexed = 0;
+        {
+            let mut iter = self.shared.db().new_iter().map_err(GraphError::Storage)?;
+            iter.seek(&[NODE_PREFIX]);
+
+            let mut key = Vec::new();
+            let mut value = Vec::new();
+            while iter.valid() {
+                iter.current(&mut key, &mut value);
+                if key.first() != Some(&NODE_PREFIX) {
+                    break;
+                }
+                let cid = Cid::try_from(&key[1..])
+                    .map_err(|e| GraphError::NodeOperation(e.to_string()))?;
+                let node = Node::<P, M>::from_bytes(&value)
+                    .map_err(|e| GraphError::NodeOperation(e.to_string()))?;
+                let genesis = node.genesis.unwrap_or(cid);
+                batch.put(&Self::make_index_key(&genesis, &cid), &[]);
+                indexed += 1;
+                iter.advance();
+            }
+        }
+        batch.put(GENESIS_INDEX_MARKER, b"1");
+        self.shared
+            .db()
+            .write(batch, true)
+            .map_err(GraphError::Storage)?;
+        Ok(indexed)
+    }
+}
+
 impl<P, M> SharedLeveldbAccess for LeveldbNodeStorage<P, M> {
     fn shared_leveldb(&self) -> Option<Arc<SharedLeveldb>> {
         Some(self.shared.clone())
@@ -92,9 +201,10 @@ where
     P: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone + Send + Sync,
     M: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone + Send + Sync,
 {
+    /// Reads the node, seeing writes staged in the active batch.
     fn get(&self, cid: &Cid) -> Result<Option<Node<P, M>>> {
         let key = Self::make_key(cid);
-        match self.shared.db().get(&key) {
+        match self.shared.get_staged(&key) {
             Some(raw) => {
                 let node =
                     Node::from_bytes(&raw).map_err(|e| GraphError::NodeOperation(e.to_string()))?;
@@ -112,10 +222,16 @@ where
             .content_id()
             .map_err(|e| GraphError::NodeOperation(e.to_string()))?;
         let key = Self::make_key(&cid);
-        self.write_bytes(&key, &bytes)
+        self.write_bytes(&key, &bytes)?;
+        let genesis = node.genesis.unwrap_or(cid);
+        self.write_bytes(&Self::make_index_key(&genesis, &cid), &[])
     }
 
     fn delete(&self, cid: &Cid) -> Result<()> {
+        if let Some(node) = self.get(cid)? {
+            let genesis = node.genesis.unwrap_or(*cid);
+            self.delete_key(&Self::make_index_key(&genesis, cid))?;
+        }
         let key = Self::make_key(cid);
         self.delete_key(&key)
     }
@@ -130,11 +246,10 @@ where
 
         while iter.valid() {
             iter.current(&mut key, &mut value);
-            if !key.is_empty() && key[0] == 0x10 {
+            if !key.is_empty() && key[0] == NODE_PREFIX {
                 let node = Node::<P, M>::from_bytes(&value)
                     .map_err(|e| GraphError::NodeOperation(e.to_string()))?;
-                let node_cid = node
-                    .content_id()
+                let node_cid = Cid::try_from(&key[1..])
                     .map_err(|e| GraphError::NodeOperation(e.to_string()))?;
                 node_map.insert(node_cid, node.parents().to_vec());
             }
@@ -142,6 +257,98 @@ where
         }
         Ok(node_map)
     }
+
+    /// Reads the members of a genesis from the `0x11` index, seeing writes staged in
+    /// the active batch.
+    ///
+    /// Stores created before the index existed are migrated on first use.
+    fn get_nodes_by_genesis(&self, genesis: &Cid) -> Result<Option<Vec<Cid>>> {
+        self.migrate_genesis_index()?;
+
+        let prefix = Self::make_index_prefix(genesis);
+        let mut keys = BTreeSet::new();
+        let mut iter = self.shared.db().new_iter().map_err(GraphError::Storage)?;
+        iter.seek(&prefix);
+
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+        while iter.valid() {
+            iter.current(&mut key, &mut value);
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            keys.insert(key.clone());
+            iter.advance();
+        }
+        for (key, value) in self.shared.staged_with_prefix(&prefix) {
+            match value {
+                Some(_) => keys.insert(key),
+                None => keys.remove(&key),
+            };
+        }
+
+        keys.iter()
+            .map(|key| {
+                Cid::try_from(&key[prefix.len()..])
+                    .map_err(|e| GraphError::NodeOperation(e.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    /// Lists the geneses of the `0x11` index, seeking past the members of each one
+    /// so that only one key per genesis is read.
+    fn get_genesis_ids(&self) -> Result<Option<Vec<Cid>>> {
+        self.migrate_genesis_index()?;
+
+        let mut geneses = Vec::new();
+        let mut iter = self.shared.db().new_iter().map_err(GraphError::Storage)?;
+        iter.seek(&[GENESIS_INDEX_PREFIX]);
+
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+        while iter.valid() {
+            iter.current(&mut key, &mut value);
+            if key.first() != Some(&GENESIS_INDEX_PREFIX) {
+                break;
+            }
+            let genesis =
+                Cid::read_bytes(&key[1..]).map_err(|e| GraphError::NodeOperation(e.to_string()))?;
+            geneses.push(genesis);
+
+            // Every member key of this genesis sorts below the prefix successor.
+            let mut next = Self::make_index_prefix(&genesis);
+            while next.last() == Some(&0xff) {
+                next.pop();
+            }
+            if let Some(last) = next.last_mut() {
+                *last += 1;
+            }
+            iter.seek(&next);
+        }
+        geneses.sort();
+        Ok(Some(geneses))
+    }
+
+    /// Reads the head set, seeing writes staged in the active batch.
+    fn get_heads(&self, genesis: &Cid) -> Result<Option<Vec<Cid>>> {
+        let key = Self::make_heads_key(genesis);
+        match self.shared.get_staged(&key) {
+            Some(raw) => {
+                let (heads, _) = bincode::serde::decode_from_slice::<Vec<Cid>, _>(
+                    &raw,
+                    bincode::config::standard(),
+                )?;
+                Ok(Some(heads))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn put_heads(&self, genesis: &Cid, heads: &[Cid]) -> Result<()> {
+        let value = bincode::serde::encode_to_vec(heads, bincode::config::standard())?;
+        self.write_bytes(&Self::make_heads_key(genesis), &value)
+    }
 }
 
 #[cfg(test)]
@@ -248,4 +455,143 @@ mod tests {
 
         assert!(storage.get(&cid).unwrap().is_none());
     }
+
+    #[test]
+    fn test_get_nodes_by_genesis_uses_index() {
+        let temp_dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, String>::open(temp_dir.path());
+
+        let genesis = create_test_node("genesis");
+        let genesis_cid = genesis.content_id().unwrap();
+        let child = Node::new_child(
+            "child".to_string(),
+            vec![genesis_cid],
+            genesis_cid,
+            2,
+            "metadata".to_string(),
+        );
+        let child_cid = child.content_id().unwrap();
+        let other = create_test_node("other");
+        let other_cid = other.content_id().unwrap();
+
+        storage.put(&genesis).unwrap();
+        storage.put(&child).unwrap();
+        storage.put(&other).unwrap();
+
+        let mut members = storage.get_nodes_by_genesis(&genesis_cid).unwrap().unwrap();
+        members.sort();
+        let mut expected = vec![genesis_cid, child_cid];
+        expected.sort();
+        assert_eq!(members, expected);
+
+        let others = storage.get_nodes_by_genesis(&other_cid).unwrap().unwrap();
+        assert_eq!(others, vec![other_cid]);
+
+        let mut expected = vec![genesis_cid, other_cid];
+        expected.sort();
+        assert_eq!(storage.get_genesis_ids().unwrap(), Some(expected));
+
+        storage.delete(&child_cid).unwrap();
+        let members = storage.get_nodes_by_genesis(&genesis_cid).unwrap().unwrap();
+        assert_eq!(members, vec![genesis_cid]);
+    }
+
+    #[test]
+    fn test_migrate_genesis_index_for_legacy_nodes() {
+        let temp_dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, String>::open(temp_dir.path());
+
+        let node = create_test_node("legacy");
+        let cid = node.content_id().unwrap();
+        let key = LeveldbNodeStorage::<String, String>::make_key(&cid);
+        storage
+            .shared
+            .db()
+            .put(&key, &node.to_bytes().unwrap())
+            .unwrap();
+
+        assert_eq!(storage.migrate_genesis_index().unwrap(), 1);
+        assert_eq!(storage.migrate_genesis_index().unwrap(), 0);
+        assert_eq!(storage.get_nodes_by_genesis(&cid).unwrap(), Some(vec![cid]));
+    }
+
+    #[test]
+    fn test_reads_see_staged_writes() {
+        let temp_dir = tempdir().unwrap();
+        let shared = SharedLeveldb::open(temp_dir.path()).unwrap();
+        let storage = LeveldbNodeStorage::<String, String>::new(shared.clone());
+        let genesis = create_test_node("genesis");
+        let genesis_cid = genesis.content_id().unwrap();
+        storage.put(&genesis).unwrap();
+        storage.put_heads(&genesis_cid, &[genesis_cid]).unwrap();
+
+        let child = Node::new_child(
+            "child".to_string(),
+            vec![genesis_cid],
+            genesis_cid,
+            2,
+            "metadata".to_string(),
+        );
+        let child_cid = child.content_id().unwrap();
+        let mut with_child = vec![genesis_cid, child_cid];
+        with_child.sort_by_key(|cid| cid.to_bytes());
+        {
+            let _guard = shared.begin_batch().unwrap();
+            storage.put(&child).unwrap();
+            storage.put_heads(&genesis_cid, &[child_cid]).unwrap();
+            assert!(storage.get(&child_cid).unwrap().is_some());
+            assert_eq!(
+                storage.get_nodes_by_genesis(&genesis_cid).unwrap(),
+                Some(with_child.clone())
+            );
+            assert_eq!(
+                storage.get_heads(&genesis_cid).unwrap(),
+                Some(vec![child_cid])
+            );
+        }
+
+        // Dropping the batch discards the staged writes.
+        assert!(storage.get(&child_cid).unwrap().is_none());
+        assert_eq!(
+            storage.get_nodes_by_genesis(&genesis_cid).unwrap(),
+            Some(vec![genesis_cid])
+        );
+        assert_eq!(
+            storage.get_heads(&genesis_cid).unwrap(),
+            Some(vec![genesis_cid])
+        );
+
+        storage.put(&child).unwrap();
+        let guard = shared.begin_batch().unwrap();
+        storage.delete(&child_cid).unwrap();
+        assert_eq!(
+            storage.get_nodes_by_genesis(&genesis_cid).unwrap(),
+            Some(vec![genesis_cid])
+        );
+        drop(guard);
+        assert_eq!(
+            storage.get_nodes_by_genesis(&genesis_cid).unwrap(),
+            Some(with_child)
+        );
+    }
+
+    #[test]
+    fn test_put_and_get_heads() {
+        let temp_dir = tempdir().unwrap();
+        let storage = LeveldbNodeStorage::<String, String>::open(temp_dir.path());
+        let genesis = create_test_node("genesis").content_id().unwrap();
+        let head_a = create_test_node("head-a").content_id().unwrap();
+        let head_b = create_test_node("head-b").content_id().unwrap();
+
+        assert_eq!(storage.get_heads(&genesis).unwrap(), None);
+
+        storage.put_heads(&genesis, &[head_a, head_b]).unwrap();
+        assert_eq!(
+            storage.get_heads(&genesis).unwrap(),
+            Some(vec![head_a, head_b])
+        );
+
+        storage.put_heads(&genesis, &[head_b]).unwrap();
+        assert_eq!(storage.get_heads(&genesis).unwrap(), Some(vec![head_b]));
+    }
 }
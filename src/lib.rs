@@ -5,3 +5,4 @@ pub mod graph;
 pub mod masl;
 pub mod repo;
 pub mod storage;
+pub mod sync;
@@ -1 +1,267 @@
+//! MASL: metadata for content-addressed resources.
+//!
+//! A [`Resource`] describes a blob stored outside the DAG (its CID, media type,
+//! size, name and free-form extra fields). A [`Bundle`] groups resources by path
+//! so a single document can reference several attachments. Both records are
+//! encoded as canonical DAG-CBOR with `src` as a CID link, and are themselves
+//! addressed by a dag-cbor CID.
 
+use crate::dasl::cid::ContentId;
+use crate::dasl::dag_cbor::{self, DAG_CBOR_CODE};
+use crate::dasl::error::{DaslError, Result};
+use cid::Cid;
+use multihash::Multihash;
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+const SHA2_256_CODE: u64 = 0x12;
+
+/// Metadata record for a single content-addressed resource.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Resource {
+    /// CID of the resource bytes.
+    pub src: Cid,
+    /// Media type, e.g. `image/png`.
+    pub content_type: String,
+    /// Size of the resource in bytes.
+    pub size: u64,
+    /// Human readable name, e.g. the original file name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Application specific fields.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Resource {
+    pub fn new(src: Cid, content_type: impl Into<String>, size: u64) -> Self {
+        Self {
+            src,
+            content_type: content_type.into(),
+            size,
+            name: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// Describes `data`, addressing it with a raw sha2-256 CID.
+    pub fn for_data(data: &[u8], content_type: impl Into<String>) -> Result<Self> {
+        let ContentId(src) = ContentId::new(data)?;
+        Ok(Self::new(src, content_type, data.len() as u64))
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_extra(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
+
+    /// Checks that `data` matches the recorded size and raw `src` CID.
+    pub fn verify(&self, data: &[u8]) -> bool {
+        self.size == data.len() as u64 && ContentId(self.src).verify(data)
+    }
+
+    /// Encodes the record as canonical DAG-CBOR.
+    pub fn to_dag_cbor(&self) -> Result<Vec<u8>> {
+        dag_cbor::to_vec(&self.to_value()?)
+    }
+
+    /// Decodes a record produced by [`Self::to_dag_cbor`].
+    pub fn from_dag_cbor(buf: &[u8]) -> Result<Self> {
+        Self::from_value(dag_cbor::from_slice(buf)?)
+    }
+
+    /// Returns the dag-cbor CID of the record.
+    pub fn cid(&self) -> Result<Cid> {
+        dag_cbor_cid(&self.to_dag_cbor()?)
+    }
+
+    fn to_value(&self) -> Result<Value> {
+        let Value::Map(mut fields) = serde_cbor::value::to_value(self)? else {
+            return Err(not_a_map("resource"));
+        };
+        fields.insert(text_key("src"), dag_cbor::link(&self.src));
+        Ok(Value::Map(fields))
+    }
+
+    fn from_value(value: Value) -> Result<Self> {
+        let Value::Map(mut fields) = value else {
+            return Err(not_a_map("resource"));
+        };
+        let src = fields
+            .get(&text_key("src"))
+            .ok_or_else(|| DaslError::Deserialization {
+                message: "resource lacks src".to_string(),
+            })
+            .and_then(dag_cbor::parse_link)?;
+        fields.insert(text_key("src"), Value::Bytes(src.to_bytes()));
+        serde_cbor::value::from_value(Value::Map(fields)).map_err(|e| DaslError::Deserialization {
+            message: format!("Failed to deserialize resource: {e}"),
+        })
+    }
+}
+
+/// A set of resources keyed by path (e.g. `/cover.png`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    resources: BTreeMap<String, Resource>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a resource, returning the one previously stored under `path`.
+    pub fn insert(&mut self, path: impl Into<String>, resource: Resource) -> Option<Resource> {
+        self.resources.insert(path.into(), resource)
+    }
+
+    pub fn with_resource(mut self, path: impl Into<String>, resource: Resource) -> Self {
+        self.insert(path, resource);
+        self
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<Resource> {
+        self.resources.remove(path)
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Resource> {
+        self.resources.get(path)
+    }
+
+    /// Iterates resources in path order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Resource)> {
+        self.resources.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    /// Encodes the bundle as canonical DAG-CBOR.
+    pub fn to_dag_cbor(&self) -> Result<Vec<u8>> {
+        let mut resources = BTreeMap::new();
+        for (path, resource) in &self.resources {
+            resources.insert(Value::Text(path.clone()), resource.to_value()?);
+        }
+        let mut fields = BTreeMap::new();
+        fields.insert(text_key("resources"), Value::Map(resources));
+        dag_cbor::to_vec(&Value::Map(fields))
+    }
+
+    /// Decodes a bundle produced by [`Self::to_dag_cbor`].
+    pub fn from_dag_cbor(buf: &[u8]) -> Result<Self> {
+        let Value::Map(mut fields) = dag_cbor::from_slice(buf)? else {
+            return Err(not_a_map("bundle"));
+        };
+        let Some(Value::Map(entries)) = fields.remove(&text_key("resources")) else {
+            return Err(DaslError::Deserialization {
+                message: "bundle lacks resources".to_string(),
+            });
+        };
+        let mut resources = BTreeMap::new();
+        for (path, value) in entries {
+            let Value::Text(path) = path else {
+                return Err(DaslError::Deserialization {
+                    message: "bundle path is not a string".to_string(),
+                });
+            };
+            resources.insert(path, Resource::from_value(value)?);
+        }
+        Ok(Self { resources })
+    }
+
+    /// Returns the dag-cbor CID of the bundle.
+    pub fn cid(&self) -> Result<Cid> {
+        dag_cbor_cid(&self.to_dag_cbor()?)
+    }
+}
+
+fn dag_cbor_cid(bytes: &[u8]) -> Result<Cid> {
+    let hash = Sha256::digest(bytes);
+    let mh = Multihash::<64>::wrap(SHA2_256_CODE, &hash)?;
+    Ok(Cid::new_v1(DAG_CBOR_CODE, mh))
+}
+
+fn text_key(key: &str) -> Value {
+    Value::Text(key.to_string())
+}
+
+fn not_a_map(record: &str) -> DaslError {
+    DaslError::Deserialization {
+        message: format!("{record} is not a map"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_resource() -> Resource {
+        Resource::for_data(b"\x89PNG fake image", "image/png")
+            .unwrap()
+            .with_name("cover.png")
+            .with_extra("width", "640")
+    }
+
+    #[test]
+    fn test_resource_describes_data() {
+        let resource = sample_resource();
+        assert_eq!(resource.size, 15);
+        assert!(resource.verify(b"\x89PNG fake image"));
+        assert!(!resource.verify(b"other bytes"));
+    }
+
+    #[test]
+    fn test_resource_dag_cbor_roundtrip() {
+        let resource = sample_resource();
+        let bytes = resource.to_dag_cbor().unwrap();
+        assert_eq!(Resource::from_dag_cbor(&bytes).unwrap(), resource);
+
+        let Value::Map(fields) = dag_cbor::from_slice(&bytes).unwrap() else {
+            panic!("expected map");
+        };
+        assert_eq!(
+            dag_cbor::parse_link(&fields[&text_key("src")]).unwrap(),
+            resource.src
+        );
+        assert!(fields.contains_key(&text_key("content-type")));
+    }
+
+    #[test]
+    fn test_bundle_cid_is_deterministic() {
+        let readme = Resource::for_data(b"# readme", "text/markdown").unwrap();
+        let cover = sample_resource();
+
+        let a = Bundle::new()
+            .with_resource("/readme.md", readme.clone())
+            .with_resource("/cover.png", cover.clone());
+        let b = Bundle::new()
+            .with_resource("/cover.png", cover)
+            .with_resource("/readme.md", readme);
+
+        let cid = a.cid().unwrap();
+        assert_eq!(cid, b.cid().unwrap());
+        assert_eq!(cid.codec(), DAG_CBOR_CODE);
+
+        let decoded = Bundle::from_dag_cbor(&a.to_dag_cbor().unwrap()).unwrap();
+        assert_eq!(decoded, a);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(
+            decoded.get("/cover.png").unwrap().name.as_deref(),
+            Some("cover.png")
+        );
+    }
+}
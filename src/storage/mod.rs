@@ -1,3 +1,5 @@
+mod refs;
 mod shared_leveldb;
 
+pub use refs::{RefStore, Tag};
 pub use shared_leveldb::{BatchError, LeveldbBatchGuard, SharedLeveldb, SharedLeveldbAccess};
@@ -0,0 +1,263 @@
+use crate::crdt::operation::Author;
+use crate::storage::SharedLeveldb;
+use cid::Cid;
+use rusty_leveldb::{LdbIterator, Status, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// LevelDB namespace for named refs, keyed by `genesis | name`.
+const REF_PREFIX: u8 = 0x20;
+/// LevelDB namespace for tags, keyed by `genesis | name`.
+const TAG_PREFIX: u8 = 0x21;
+
+/// A raw LevelDB key and value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// An immutable, named label for a version of a document.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+    pub genesis: Cid,
+    pub name: String,
+    /// The tagged version.
+    pub target: Cid,
+    pub author: Author,
+    /// Packed creation timestamp as reported by the creating replica, see
+    /// `crdt::timestamp`. Informational only: tags are never ordered by it.
+    pub timestamp: u64,
+    pub message: Option<String>,
+}
+
+/// Named pointers to versions of a document: movable refs such as branch tips, and
+/// [`Tag`]s.
+///
+/// Writes go into the active batch when there is one, so a ref moves atomically with
+/// the commit that produced its new target. Refs are local to a replica, while sync
+/// exchanges tags.
+#[derive(Clone)]
+pub struct RefStore {
+    shared: Arc<SharedLeveldb>,
+}
+
+impl RefStore {
+    pub fn new(shared: Arc<SharedLeveldb>) -> Self {
+        Self { shared }
+    }
+
+    /// Returns the version `name` of `genesis` points to, seeing writes staged in the
+    /// active batch.
+    pub fn get(&self, genesis: &Cid, name: &str) -> Result<Option<Cid>, Status> {
+        self.shared
+            .get_staged(&Self::make_key(genesis, name))
+            .map(|raw| decode_cid(&raw))
+            .transpose()
+    }
+
+    /// Returns the refs of `genesis` sorted by name.
+    ///
+    /// Only committed refs are listed; writes staged in the active batch are not.
+    pub fn list(&self, genesis: &Cid) -> Result<Vec<(String, Cid)>, Status> {
+        let prefix = Self::make_prefix(REF_PREFIX, genesis);
+        self.scan(&prefix)?
+            .into_iter()
+            .map(|(key, value)| {
+                let name = String::from_utf8(key[prefix.len()..].to_vec())
+                    .map_err(|e| Status::new(StatusCode::Corruption, &e.to_string()))?;
+                Ok((name, decode_cid(&value)?))
+            })
+            .collect()
+    }
+
+    /// Points `name` of `genesis` at `target`.
+    pub fn put(&self, genesis: &Cid, name: &str, target: &Cid) -> Result<(), Status> {
+        let key = Self::make_key(genesis, name);
+        let value = target.to_bytes();
+        if self
+            .shared
+            .with_active_batch(|batch| batch.put(&key, &value))
+            .is_none()
+        {
+            self.shared.db().put(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Removes `name` of `genesis`.
+    pub fn delete(&self, genesis: &Cid, name: &str) -> Result<(), Status> {
+        let key = Self::make_key(genesis, name);
+        if self
+            .shared
+            .with_active_batch(|batch| batch.delete(&key))
+            .is_none()
+        {
+            self.shared.db().delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Returns tag `name` of `genesis`, seeing writes staged in the active batch.
+    pub fn get_tag(&self, genesis: &Cid, name: &str) -> Result<Option<Tag>, Status> {
+        self.shared
+            .get_staged(&Self::make_tag_key(genesis, name))
+            .map(|raw| decode_tag(&raw))
+            .transpose()
+    }
+
+    /// Returns the committed tags of `genesis` sorted by name.
+    pub fn list_tags(&self, genesis: &Cid) -> Result<Vec<Tag>, Status> {
+        self.scan(&Self::make_prefix(TAG_PREFIX, genesis))?
+            .iter()
+            .map(|(_, value)| decode_tag(value))
+            .collect()
+    }
+
+    /// Returns the committed tags of every genesis.
+    pub fn all_tags(&self) -> Result<Vec<Tag>, Status> {
+        self.scan(&[TAG_PREFIX])?
+            .iter()
+            .map(|(_, value)| decode_tag(value))
+            .collect()
+    }
+
+    /// Stores `tag`, replacing a tag of the same name.
+    pub fn put_tag(&self, tag: &Tag) -> Result<(), Status> {
+        let key = Self::make_tag_key(&tag.genesis, &tag.name);
+        let value = bincode::serde::encode_to_vec(tag, bincode::config::standard())
+            .map_err(|e| Status::new(StatusCode::InvalidData, &e.to_string()))?;
+        if self
+            .shared
+            .with_active_batch(|batch| batch.put(&key, &value))
+            .is_none()
+        {
+            self.shared.db().put(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the committed entries whose key starts with `prefix`, in key order.
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<Entry>, Status> {
+        let mut entries = Vec::new();
+        let mut iter = self.shared.db().new_iter()?;
+        iter.seek(prefix);
+
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+        while iter.valid() {
+            iter.current(&mut key, &mut value);
+            if !key.starts_with(prefix) {
+                break;
+            }
+            entries.push((key.clone(), value.clone()));
+            iter.advance();
+        }
+        Ok(entries)
+    }
+
+    /// Builds the prefix shared by all refs or tags of a genesis.
+    ///
+    /// CID bytes are self-delimiting, so the name is the rest of the key.
+    fn make_prefix(namespace: u8, genesis: &Cid) -> Vec<u8> {
+        let genesis_bytes = genesis.to_bytes();
+        let mut v = Vec::with_capacity(1 + genesis_bytes.len());
+        v.push(namespace);
+        v.extend_from_slice(&genesis_bytes);
+        v
+    }
+
+    /// Builds the ref key `0x20 | genesis | name`.
+    fn make_key(genesis: &Cid, name: &str) -> Vec<u8> {
+        let mut v = Self::make_prefix(REF_PREFIX, genesis);
+        v.extend_from_slice(name.as_bytes());
+        v
+    }
+
+    /// Builds the tag key `0x21 | genesis | name`.
+    fn make_tag_key(genesis: &Cid, name: &str) -> Vec<u8> {
+        let mut v = Self::make_prefix(TAG_PREFIX, genesis);
+        v.extend_from_slice(name.as_bytes());
+        v
+    }
+}
+
+fn decode_cid(raw: &[u8]) -> Result<Cid, Status> {
+    Cid::try_from(raw).map_err(|e| Status::new(StatusCode::Corruption, &e.to_string()))
+}
+
+fn decode_tag(raw: &[u8]) -> Result<Tag, Status> {
+    bincode::serde::decode_from_slice(raw, bincode::config::standard())
+        .map(|(tag, _)| tag)
+        .map_err(|e| Status::new(StatusCode::Corruption, &e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn cid(seed: &[u8]) -> Cid {
+        Cid::new_v1(0x55, multihash::Multihash::<64>::wrap(0x12, seed).unwrap())
+    }
+
+    #[test]
+    fn refs_are_scoped_per_genesis_and_follow_the_batch() {
+        let dir = tempdir().unwrap();
+        let shared = SharedLeveldb::open(dir.path()).expect("open shared db");
+        let refs = RefStore::new(shared.clone());
+        let (genesis, other) = (cid(b"genesis"), cid(b"other"));
+
+        refs.put(&genesis, "main", &cid(b"v1")).unwrap();
+        refs.put(&genesis, "feature", &cid(b"v2")).unwrap();
+        refs.put(&other, "main", &cid(b"v3")).unwrap();
+        assert_eq!(
+            refs.list(&genesis).unwrap(),
+            vec![("feature".into(), cid(b"v2")), ("main".into(), cid(b"v1"))]
+        );
+
+        {
+            let _guard = shared.begin_batch().unwrap();
+            refs.put(&genesis, "main", &cid(b"v4")).unwrap();
+            refs.delete(&genesis, "feature").unwrap();
+            assert_eq!(refs.get(&genesis, "main").unwrap(), Some(cid(b"v4")));
+            assert_eq!(refs.get(&genesis, "feature").unwrap(), None);
+        }
+        assert_eq!(refs.get(&genesis, "main").unwrap(), Some(cid(b"v1")));
+
+        let guard = shared.begin_batch().unwrap();
+        refs.delete(&genesis, "feature").unwrap();
+        guard.commit().unwrap();
+        assert_eq!(refs.get(&genesis, "feature").unwrap(), None);
+        assert_eq!(refs.get(&other, "main").unwrap(), Some(cid(b"v3")));
+    }
+
+    #[test]
+    fn tags_roundtrip_apart_from_refs() {
+        let dir = tempdir().unwrap();
+        let shared = SharedLeveldb::open(dir.path()).expect("open shared db");
+        let refs = RefStore::new(shared);
+        let (genesis, other) = (cid(b"genesis"), cid(b"other"));
+        let tag = |genesis: Cid, name: &str| Tag {
+            genesis,
+            name: name.into(),
+            target: cid(b"v1"),
+            author: "alice".into(),
+            timestamp: 7,
+            message: Some("first release".into()),
+        };
+
+        refs.put(&genesis, "v1", &cid(b"v2")).unwrap();
+        refs.put_tag(&tag(genesis, "v1")).unwrap();
+        refs.put_tag(&tag(genesis, "approved")).unwrap();
+        refs.put_tag(&tag(other, "v1")).unwrap();
+
+        assert_eq!(
+            refs.get_tag(&genesis, "v1").unwrap(),
+            Some(tag(genesis, "v1"))
+        );
+        assert_eq!(refs.get(&genesis, "v1").unwrap(), Some(cid(b"v2")));
+        assert_eq!(
+            refs.list_tags(&genesis).unwrap(),
+            vec![tag(genesis, "approved"), tag(genesis, "v1")]
+        );
+        assert_eq!(refs.all_tags().unwrap().len(), 3);
+        assert_eq!(refs.list(&genesis).unwrap().len(), 1);
+    }
+}
@@ -1,6 +1,15 @@
+use crate::crdt::operation::Timestamp;
+use crate::crdt::timestamp::{global_clock, NodeId};
 use rusty_leveldb::{Options, Status, WriteBatch, DB as Database};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
+use ulid::Ulid;
+
+/// Key holding the replica ID generated when the store was first opened.
+const REPLICA_ID_KEY: &[u8] = b"\x00meta/replica-id";
+/// Key holding the highest timestamp committed by this replica.
+const CLOCK_KEY: &[u8] = b"\x00meta/clock";
 
 #[derive(Debug)]
 pub enum BatchError {
@@ -13,20 +22,46 @@ pub enum BatchError {
 pub struct SharedLeveldb {
     db: Mutex<Database>,
     active_batch: Mutex<Option<WriteBatch>>,
+    replica_id: NodeId,
+    clock_watermark: AtomicU64,
     #[cfg(test)]
     commit_fail_status: Mutex<Option<Status>>,
 }
 
 impl SharedLeveldb {
+    /// Opens (or creates) the database.
+    ///
+    /// On first open a random replica ID is generated and persisted. The persisted
+    /// clock watermark is fed to the global clock so that a restarted process never
+    /// issues a timestamp lower than one it has already committed.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Arc<Self>, Status> {
         let opts = Options {
             create_if_missing: true,
             ..Default::default()
         };
-        let db = Database::open(path, opts)?;
+        let mut db = Database::open(path, opts)?;
+        let replica_id = match db.get(REPLICA_ID_KEY).as_deref().and_then(decode_u64) {
+            Some(id) => id,
+            None => {
+                let id = (Ulid::new().random() as u64).max(1);
+                db.put(REPLICA_ID_KEY, &id.to_be_bytes())?;
+                db.flush()?;
+                id
+            }
+        };
+        let watermark = db
+            .get(CLOCK_KEY)
+            .as_deref()
+            .and_then(decode_u64)
+            .unwrap_or(0);
+        if watermark > 0 {
+            global_clock().restore(watermark);
+        }
         Ok(Arc::new(Self {
             db: Mutex::new(db),
             active_batch: Mutex::new(None),
+            replica_id,
+            clock_watermark: AtomicU64::new(watermark),
             #[cfg(test)]
             commit_fail_status: Mutex::new(None),
         }))
@@ -84,6 +119,63 @@ impl SharedLeveldb {
         slot.as_mut().map(f)
     }
 
+    /// Reads `key`, seeing writes staged in the active batch.
+    pub fn get_staged(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let staged = self.with_active_batch(|batch| {
+            batch
+                .iter()
+                .filter(|(staged_key, _)| *staged_key == key)
+                .last()
+                .map(|(_, value)| value.map(<[u8]>::to_vec))
+        });
+        match staged.flatten() {
+            Some(value) => value,
+            None => self.db().get(key),
+        }
+    }
+
+    /// Returns the writes staged in the active batch whose key starts with `prefix`,
+    /// in staging order. Deletes have a `None` value.
+    pub fn staged_with_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Option<Vec<u8>>)> {
+        self.with_active_batch(|batch| {
+            batch
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, value)| (key.to_vec(), value.map(<[u8]>::to_vec)))
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    /// Returns the ID identifying this store as a replica.
+    pub fn replica_id(&self) -> NodeId {
+        self.replica_id
+    }
+
+    /// Returns the highest timestamp recorded via [`Self::record_timestamp`].
+    pub fn clock_watermark(&self) -> Timestamp {
+        self.clock_watermark.load(Ordering::Acquire)
+    }
+
+    /// Records that `timestamp` has been used by this replica.
+    ///
+    /// The watermark is written into the active batch when there is one, so it is
+    /// persisted atomically with the data that used the timestamp.
+    pub fn record_timestamp(&self, timestamp: Timestamp) -> Result<(), Status> {
+        let watermark = self
+            .clock_watermark
+            .fetch_max(timestamp, Ordering::AcqRel)
+            .max(timestamp);
+        let value = watermark.to_be_bytes();
+        if self
+            .with_active_batch(|batch| batch.put(CLOCK_KEY, &value))
+            .is_none()
+        {
+            self.db().put(CLOCK_KEY, &value)?;
+        }
+        Ok(())
+    }
+
     pub fn db(&self) -> MutexGuard<'_, Database> {
         self.db.lock().expect("Database lock poisoned")
     }
@@ -93,6 +185,10 @@ impl SharedLeveldb {
     }
 }
 
+fn decode_u64(raw: &[u8]) -> Option<u64> {
+    raw.try_into().ok().map(u64::from_be_bytes)
+}
+
 pub struct LeveldbBatchGuard<'a> {
     shared: &'a SharedLeveldb,
     committed: bool,
@@ -193,4 +289,38 @@ mod tests {
             "value should not be persisted when batch guard is dropped without commit"
         );
     }
+
+    #[test]
+    fn replica_id_is_persisted_across_reopen() {
+        let dir = tempdir().unwrap();
+        let first = {
+            let shared = SharedLeveldb::open(dir.path()).expect("open shared db");
+            shared.replica_id()
+        };
+        let shared = SharedLeveldb::open(dir.path()).expect("reopen shared db");
+        assert_ne!(first, 0);
+        assert_eq!(shared.replica_id(), first);
+
+        let other_dir = tempdir().unwrap();
+        let other = SharedLeveldb::open(other_dir.path()).expect("open other db");
+        assert_ne!(other.replica_id(), first);
+    }
+
+    #[test]
+    fn clock_watermark_advances_clock_on_reopen() {
+        let dir = tempdir().unwrap();
+        let watermark = global_clock().last() + 5_000_000_000;
+        {
+            let shared = SharedLeveldb::open(dir.path()).expect("open shared db");
+            let guard = shared.begin_batch().expect("begin batch");
+            shared
+                .record_timestamp(watermark)
+                .expect("record timestamp");
+            guard.commit().expect("commit batch");
+        }
+
+        let shared = SharedLeveldb::open(dir.path()).expect("reopen shared db");
+        assert_eq!(shared.clock_watermark(), watermark);
+        assert!(global_clock().now().packed() > watermark);
+    }
 }
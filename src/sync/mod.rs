@@ -0,0 +1,131 @@
+//! Transport-agnostic replication of DAG history between repositories.
+//!
+//! A sync round is two messages:
+//!
+//! 1. The requesting replica sends [`SyncMessage::Heads`] with its heads per genesis
+//!    (see [`Repo::sync_heads`](crate::repo::Repo::sync_heads)).
+//! 2. The peer answers with [`SyncMessage::Changes`] holding every node (and the
+//!    operation that produced it) reachable from its own heads but not from the
+//!    advertised ones, in causal order
+//!    (see [`Repo::changes_since`](crate::repo::Repo::changes_since)), and its tags
+//!    of the advertised documents and of the documents those nodes belong to, so
+//!    tags replicate even for documents that are already in sync.
+//!
+//! The requester imports the changes with
+//! [`Repo::apply_changes`](crate::repo::Repo::apply_changes) and the tags with
+//! [`Repo::apply_tags`](crate::repo::Repo::apply_tags). Messages are encoded as
+//! CBOR byte buffers, so they can travel over any transport.
+
+use crate::convergence::metadata::ContentMetadata;
+use crate::crdt::error::{CrdtError, Result};
+use crate::crdt::operation::Operation;
+use crate::dasl::node::Node;
+use crate::storage::Tag;
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+/// Heads of a single genesis as known by a replica.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisHeads {
+    pub genesis: Cid,
+    pub heads: Vec<Cid>,
+}
+
+/// A DAG node shipped to a peer together with the operation that produced it.
+///
+/// `operation` is `None` when the sender has no operation recorded for the node;
+/// the receiver then synthesises one from the node.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "P: Serialize + for<'a> Deserialize<'a>")]
+pub struct SyncEntry<P> {
+    pub node: Node<P, ContentMetadata>,
+    pub operation: Option<Operation<Cid, P>>,
+}
+
+/// Outcome of applying a sync response, see
+/// [`Repo::sync_apply`](crate::repo::Repo::sync_apply).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Number of nodes that were not present before.
+    pub imported: usize,
+    /// Tags of the peer left out because a different local tag has the same name.
+    pub tag_conflicts: Vec<Tag>,
+}
+
+/// Messages exchanged during a sync round.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "P: Serialize + for<'a> Deserialize<'a>")]
+pub enum SyncMessage<P> {
+    /// Heads advertised by the requesting replica.
+    Heads(Vec<GenesisHeads>),
+    /// Nodes the requesting replica lacks, parents before children, and the answering
+    /// replica's tags of the advertised documents and the documents they belong to.
+    Changes {
+        entries: Vec<SyncEntry<P>>,
+        #[serde(default)]
+        tags: Vec<Tag>,
+    },
+}
+
+impl<P> SyncMessage<P>
+where
+    P: Serialize + for<'a> Deserialize<'a>,
+{
+    /// Encodes the message into a CBOR byte buffer.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_cbor::to_vec(self)
+            .map_err(|err| CrdtError::Internal(format!("failed to encode sync message: {err}")))
+    }
+
+    /// Decodes a message produced by [`Self::to_bytes`].
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        serde_cbor::from_slice(buf)
+            .map_err(|err| CrdtError::Internal(format!("failed to decode sync message: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::operation::OperationType;
+
+    #[test]
+    fn sync_message_roundtrip() {
+        let genesis = Node::new_genesis("root".to_string(), 1, ContentMetadata::default());
+        let genesis_cid = genesis.content_id().unwrap();
+        let op = Operation::new(
+            genesis_cid,
+            OperationType::Create("root".to_string()),
+            "alice".to_string(),
+        );
+
+        let heads = SyncMessage::<String>::Heads(vec![GenesisHeads {
+            genesis: genesis_cid,
+            heads: vec![genesis_cid],
+        }]);
+        let decoded = SyncMessage::<String>::from_bytes(&heads.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, heads);
+
+        let changes = SyncMessage::Changes {
+            entries: vec![SyncEntry {
+                node: genesis,
+                operation: Some(op),
+            }],
+            tags: vec![Tag {
+                genesis: genesis_cid,
+                name: "v1".to_string(),
+                target: genesis_cid,
+                author: "alice".to_string(),
+                timestamp: 1,
+                message: None,
+            }],
+        };
+        let decoded = SyncMessage::<String>::from_bytes(&changes.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, changes);
+    }
+
+    #[test]
+    fn from_bytes_rejects_garbage() {
+        assert!(SyncMessage::<String>::from_bytes(b"not cbor").is_err());
+    }
+}